@@ -0,0 +1,113 @@
+use crate::summary::percentile;
+use crate::Row;
+use std::collections::{HashMap, VecDeque};
+
+/// Flags the blocks whose execution time is above a percentile of the
+/// execution times of the recent blocks of the same node.
+pub(crate) struct SlowBlocks {
+    /// The number of recent blocks the percentile is taken over.
+    window: usize,
+    /// The percentile, between 0 and 100.
+    percentile: f64,
+    /// The execution times of the recent blocks of every node, oldest first.
+    recent: HashMap<String, VecDeque<i64>>,
+}
+
+impl SlowBlocks {
+    pub(crate) fn new(window: usize, percentile: f64) -> SlowBlocks {
+        SlowBlocks {
+            window,
+            percentile,
+            recent: HashMap::new(),
+        }
+    }
+
+    /// Set whether the block of `row` is an anomaly and return a warning if it
+    /// is. A block is only compared once the node has a full window.
+    pub(crate) fn check(&mut self, row: &mut Row) -> Option<String> {
+        let recent = self.recent.entry(row.node.clone()).or_default();
+        let mut warning = None;
+        if recent.len() == self.window {
+            let mut sorted: Vec<i64> = recent.iter().copied().collect();
+            sorted.sort_unstable();
+            let threshold = percentile(&sorted, self.percentile / 100.0);
+            let anomaly = row.execution_time > threshold;
+            if anomaly {
+                warning = Some(format!(
+                    "Slow block {} at height {} on {}: executed in {} ms, above the p{} of {} ms of the last {} blocks",
+                    row.block_hash,
+                    row.block_height.height,
+                    row.node,
+                    row.execution_time,
+                    self.percentile,
+                    threshold,
+                    self.window
+                ));
+            }
+            row.anomaly = Some(anomaly);
+            recent.pop_front();
+        }
+        recent.push_back(row.execution_time);
+        warning
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::row;
+
+    /// Check a block of `node` at `height` that executed in `execution_time`.
+    fn check(
+        slow_blocks: &mut SlowBlocks,
+        node: &str,
+        height: u64,
+        execution_time: i64,
+    ) -> (Option<bool>, Option<String>) {
+        let mut row = row(node, height);
+        row.execution_time = execution_time;
+        let warning = slow_blocks.check(&mut row);
+        (row.anomaly, warning)
+    }
+
+    #[test]
+    fn blocks_are_compared_once_the_window_is_full() {
+        let mut slow_blocks = SlowBlocks::new(3, 95.0);
+        for (height, execution_time) in [(1, 10), (2, 900), (3, 12)] {
+            assert_eq!(
+                check(&mut slow_blocks, "node-0", height, execution_time),
+                (None, None)
+            );
+        }
+        // the p95 of 10, 900 and 12 is 900.
+        assert_eq!(check(&mut slow_blocks, "node-0", 4, 900).0, Some(false));
+        // the window has moved on to 900, 12 and 900.
+        assert_eq!(check(&mut slow_blocks, "node-0", 5, 901).0, Some(true));
+    }
+
+    #[test]
+    fn slow_blocks_are_warned_about() {
+        let mut slow_blocks = SlowBlocks::new(4, 50.0);
+        for height in 1..=4 {
+            check(&mut slow_blocks, "node-0", height, 10 * height as i64);
+        }
+        let (anomaly, warning) = check(&mut slow_blocks, "node-0", 5, 21);
+        assert_eq!(anomaly, Some(true));
+        let warning = warning.unwrap();
+        assert!(warning.contains("at height 5 on node-0"), "{}", warning);
+        assert!(
+            warning.contains("above the p50 of 20 ms of the last 4 blocks"),
+            "{}",
+            warning
+        );
+    }
+
+    #[test]
+    fn nodes_have_their_own_window() {
+        let mut slow_blocks = SlowBlocks::new(2, 95.0);
+        check(&mut slow_blocks, "node-0", 1, 10);
+        check(&mut slow_blocks, "node-0", 2, 10);
+        assert_eq!(check(&mut slow_blocks, "node-1", 1, 500), (None, None));
+        assert_eq!(check(&mut slow_blocks, "node-0", 3, 500).0, Some(true));
+    }
+}
@@ -0,0 +1,108 @@
+use crate::Row;
+use concordium_rust_sdk::common::SerdeSerialize;
+use concordium_rust_sdk::types::{hashes::BlockHash, BakerId};
+use std::collections::{BTreeMap, HashSet};
+
+/// The timings of the blocks produced by a baker.
+#[derive(SerdeSerialize)]
+pub(crate) struct BakerRow {
+    #[serde(rename = "Baker id")]
+    baker: BakerId,
+    #[serde(rename = "Blocks")]
+    blocks: u64,
+    #[serde(rename = "Transactions")]
+    transactions: u64,
+    #[serde(rename = "Mean execution time (millis)")]
+    mean_execution_time: f64,
+    #[serde(rename = "Mean propagation time (millis)")]
+    mean_propagation_time: f64,
+}
+
+/// The measurements of the blocks of a baker.
+#[derive(Default)]
+struct Blocks {
+    hashes: HashSet<BlockHash>,
+    transactions: u64,
+    /// The number of rows and the sums of their times.
+    rows: u64,
+    execution_time: i64,
+    propagation_time: i64,
+}
+
+/// Group the `rows` by the baker of their block. A block that is in the rows
+/// of several nodes is counted once, and the times are averaged over the rows
+/// of all nodes. Rows without a baker are left out.
+pub(crate) fn by_baker(rows: &[Row]) -> Vec<BakerRow> {
+    let mut bakers: BTreeMap<BakerId, Blocks> = BTreeMap::new();
+    for row in rows {
+        let Some(baker) = row.block_baker_id else {
+            continue;
+        };
+        let blocks = bakers.entry(baker).or_default();
+        if blocks.hashes.insert(row.block_hash) {
+            blocks.transactions += row.tx_count;
+        }
+        blocks.rows += 1;
+        blocks.execution_time += row.execution_time;
+        blocks.propagation_time += row.block_propagation_time;
+    }
+    bakers
+        .into_iter()
+        .map(|(baker, blocks)| BakerRow {
+            baker,
+            blocks: blocks.hashes.len() as u64,
+            transactions: blocks.transactions,
+            mean_execution_time: blocks.execution_time as f64 / blocks.rows as f64,
+            mean_propagation_time: blocks.propagation_time as f64 / blocks.rows as f64,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::row;
+    use concordium_rust_sdk::types::AccountIndex;
+
+    fn baker(index: u64) -> Option<BakerId> {
+        Some(BakerId {
+            id: AccountIndex { index },
+        })
+    }
+
+    #[test]
+    fn blocks_are_grouped_by_baker() {
+        // block 1 of baker 7 is seen by two nodes, block 2 of baker 3 by one.
+        let mut rows = vec![row("node-0", 1), row("node-1", 1), row("node-0", 2)];
+        rows[0].block_baker_id = baker(7);
+        rows[0].tx_count = 4;
+        rows[1].block_baker_id = baker(7);
+        rows[1].tx_count = 4;
+        rows[1].execution_time = 70;
+        rows[1].block_propagation_time = 300;
+        rows[2].block_baker_id = baker(3);
+        // genesis has no baker.
+        rows.push(row("node-0", 0));
+
+        let bakers = by_baker(&rows);
+        let summary: Vec<_> = bakers
+            .iter()
+            .map(|row| {
+                (
+                    row.baker,
+                    row.blocks,
+                    row.transactions,
+                    row.mean_execution_time,
+                    row.mean_propagation_time,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (baker(3).unwrap(), 1, 1, 50.0, 100.0),
+                (baker(7).unwrap(), 1, 4, 60.0, 200.0)
+            ]
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use anyhow::Context;
+use concordium_rust_sdk::{
+    common::{SerdeDeserialize, SerdeSerialize},
+    types::hashes::BlockHash,
+};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The part of the chain of a node that has been scanned: the blocks from
+/// `tip` down to the one above `below`, which is the next block to scan.
+#[derive(SerdeSerialize, SerdeDeserialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Scanned {
+    pub(crate) tip: BlockHash,
+    pub(crate) tip_height: u64,
+    pub(crate) below: BlockHash,
+    pub(crate) below_height: u64,
+}
+
+/// Where a walk down the chain is relative to the blocks scanned before.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Reached {
+    /// Above the tip, the blocks are new.
+    Above,
+    /// At the tip, so the blocks down to `below` can be skipped.
+    Tip,
+    /// At or below the height of the tip on another branch, e.g. after the
+    /// unfinalized tip was replaced, or inside the scanned range. The blocks
+    /// scanned before cannot be trusted to be on this chain.
+    Elsewhere,
+}
+
+impl Scanned {
+    /// Where the walk is at the block `hash` at `height`.
+    pub(crate) fn reached(&self, hash: &BlockHash, height: u64) -> Reached {
+        if height > self.tip_height {
+            Reached::Above
+        } else if height == self.tip_height && *hash == self.tip {
+            Reached::Tip
+        } else {
+            Reached::Elsewhere
+        }
+    }
+}
+
+/// The blocks scanned by previous runs, by node uri, so that the next run only
+/// scans the blocks it has not seen yet.
+pub(crate) struct Checkpoint {
+    path: PathBuf,
+    nodes: BTreeMap<String, Scanned>,
+}
+
+impl Checkpoint {
+    /// Read the checkpoint at `path`. Without a file nothing has been scanned.
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Checkpoint> {
+        let nodes = if path.exists() {
+            let contents = std::fs::read_to_string(path)
+                .context(format!("cannot read checkpoint {}", path.display()))?;
+            serde_json::from_str(&contents)
+                .context(format!("invalid checkpoint {}", path.display()))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Checkpoint {
+            path: path.to_path_buf(),
+            nodes,
+        })
+    }
+
+    pub(crate) fn get(&self, node_uri: &str) -> Option<Scanned> {
+        self.nodes.get(node_uri).copied()
+    }
+
+    pub(crate) fn update(&mut self, node_uri: &str, scanned: Scanned) {
+        self.nodes.insert(node_uri.to_string(), scanned);
+    }
+
+    /// Replace the file with the checkpoint. It is written next to it first
+    /// and then renamed, so an interrupted run keeps the previous checkpoint.
+    pub(crate) fn save(&self) -> anyhow::Result<()> {
+        let mut partial = self.path.as_os_str().to_owned();
+        partial.push(".tmp");
+        std::fs::write(&partial, serde_json::to_string_pretty(&self.nodes)?)
+            .context(format!("cannot write checkpoint {:?}", partial))?;
+        std::fs::rename(&partial, &self.path)
+            .context(format!("cannot write checkpoint {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scanned() -> Scanned {
+        Scanned {
+            tip: BlockHash::new([10; 32]),
+            tip_height: 10,
+            below: BlockHash::new([4; 32]),
+            below_height: 4,
+        }
+    }
+
+    #[test]
+    fn reaches_the_tip_only_on_the_same_branch() {
+        let scanned = scanned();
+        assert_eq!(
+            scanned.reached(&BlockHash::new([11; 32]), 11),
+            Reached::Above
+        );
+        assert_eq!(scanned.reached(&BlockHash::new([10; 32]), 10), Reached::Tip);
+        // a reorg replaced the block at the height of the tip.
+        assert_eq!(
+            scanned.reached(&BlockHash::new([99; 32]), 10),
+            Reached::Elsewhere
+        );
+        // a walk that starts inside the range never meets the tip.
+        assert_eq!(
+            scanned.reached(&BlockHash::new([7; 32]), 7),
+            Reached::Elsewhere
+        );
+    }
+
+    #[test]
+    fn saved_checkpoints_are_loaded_again() {
+        let path = std::env::temp_dir().join(format!(
+            "block-analyzer-checkpoint-{}.json",
+            std::process::id()
+        ));
+        let mut checkpoint = Checkpoint::load(&path).unwrap();
+        assert!(checkpoint.get("http://localhost:10000").is_none());
+        checkpoint.update("http://localhost:10000", scanned());
+        checkpoint.save().unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let scanned = loaded.get("http://localhost:10000").unwrap();
+        assert_eq!(scanned.tip, BlockHash::new([10; 32]));
+        assert_eq!(scanned.tip_height, 10);
+        assert_eq!(scanned.below, BlockHash::new([4; 32]));
+        assert_eq!(scanned.below_height, 4);
+        assert!(loaded.get("http://localhost:10001").is_none());
+    }
+}
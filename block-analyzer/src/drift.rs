@@ -0,0 +1,208 @@
+use crate::Row;
+use chrono::{DateTime, Utc};
+use concordium_rust_sdk::common::SerdeSerialize;
+
+/// How far the clock of a node moved away from the slot times between two of
+/// its blocks, given as their arrive and slot times: the time between their
+/// arrive times minus the time between their slot times, in milliseconds. A
+/// node with a synced clock drifts around zero, as only the delays of the
+/// blocks differ.
+pub(crate) fn drift(
+    row: (DateTime<Utc>, DateTime<Utc>),
+    previous: (DateTime<Utc>, DateTime<Utc>),
+) -> i64 {
+    let (arrive_time, slot_time) = row;
+    let (previous_arrive_time, previous_slot_time) = previous;
+    ((arrive_time - previous_arrive_time) - (slot_time - previous_slot_time)).num_milliseconds()
+}
+
+/// Fill in the drift of the `rows` of one node, which are ordered from the
+/// newest block down. The oldest row has no drift.
+pub(crate) fn fill(rows: &mut [Row]) {
+    for i in 1..rows.len() {
+        let older = (rows[i].arrive_time, rows[i].block_slot_time);
+        let row = &mut rows[i - 1];
+        row.slot_wall_drift = Some(drift((row.arrive_time, row.block_slot_time), older));
+    }
+}
+
+/// How the clock of a node affects its propagation times.
+#[derive(SerdeSerialize)]
+pub(crate) struct DriftRow {
+    #[serde(rename = "Node id")]
+    node: String,
+    #[serde(rename = "Blocks")]
+    blocks: u64,
+    #[serde(rename = "Min propagation time (millis)")]
+    min_propagation_time: i64,
+    /// The min propagation time minus the lowest one of all nodes. Besides
+    /// the offset of the clock this includes the extra network delay to the
+    /// node, if any.
+    #[serde(rename = "Clock offset (millis)")]
+    clock_offset: i64,
+    /// Blocks that were received before their slot time, which only happens
+    /// if the clock of the node or of the baker is off.
+    #[serde(rename = "Negative propagation times")]
+    negative_propagation_times: u64,
+    /// The sum of the drifts, i.e. how much the clock moved away from the slot
+    /// times between the oldest and the newest block.
+    #[serde(rename = "Total drift (millis)")]
+    total_drift: i64,
+    /// Empty if the blocks span no slot time.
+    #[serde(rename = "Drift per hour (millis)")]
+    drift_per_hour: Option<f64>,
+    #[serde(rename = "Flagged")]
+    flagged: bool,
+}
+
+/// Report the clock of every node in the `rows`, in the order the nodes first
+/// appear. A node is flagged if its total drift is more than `max_drift_ms`
+/// either way, or its clock offset is more than `max_drift_ms`.
+pub(crate) fn report(rows: &[Row], max_drift_ms: i64) -> Vec<DriftRow> {
+    let mut nodes: Vec<(&str, Vec<&Row>)> = vec![];
+    for row in rows {
+        match nodes.iter_mut().find(|(node, _)| *node == row.node) {
+            Some((_, node_rows)) => node_rows.push(row),
+            None => nodes.push((row.node.as_str(), vec![row])),
+        }
+    }
+    let min_propagation_time = |node_rows: &[&Row]| {
+        node_rows
+            .iter()
+            .map(|row| row.block_propagation_time)
+            .min()
+            .unwrap_or_default()
+    };
+    let lowest = nodes
+        .iter()
+        .map(|(_, node_rows)| min_propagation_time(node_rows))
+        .min()
+        .unwrap_or_default();
+    nodes
+        .into_iter()
+        .map(|(node, node_rows)| {
+            let min_propagation_time = min_propagation_time(&node_rows);
+            let total_drift: i64 = node_rows.iter().filter_map(|row| row.slot_wall_drift).sum();
+            let first = node_rows.iter().map(|row| row.block_slot_time).min();
+            let last = node_rows.iter().map(|row| row.block_slot_time).max();
+            let span_millis = first
+                .zip(last)
+                .map(|(first, last)| (last - first).num_milliseconds())
+                .unwrap_or_default();
+            let clock_offset = min_propagation_time - lowest;
+            DriftRow {
+                node: node.to_string(),
+                blocks: node_rows.len() as u64,
+                min_propagation_time,
+                clock_offset,
+                negative_propagation_times: node_rows
+                    .iter()
+                    .filter(|row| row.block_propagation_time < 0)
+                    .count() as u64,
+                total_drift,
+                drift_per_hour: (span_millis > 0)
+                    .then(|| total_drift as f64 * 3_600_000.0 / span_millis as f64),
+                flagged: total_drift.abs() > max_drift_ms || clock_offset > max_drift_ms,
+            }
+        })
+        .collect()
+}
+
+impl DriftRow {
+    /// A warning about the propagation times of the node, if it is flagged.
+    pub(crate) fn warning(&self) -> Option<String> {
+        self.flagged.then(|| {
+            format!(
+                "Warning: the clock of {} drifted {} ms and is {} ms behind the fastest node, so its propagation times are off",
+                self.node, self.total_drift, self.clock_offset
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::row;
+
+    /// A row of `node` at `height` that arrived `late_ms` after the time
+    /// the helper row does, e.g. because the clock of the node is ahead.
+    fn late(node: &str, height: u64, late_ms: i64) -> Row {
+        let mut row = row(node, height);
+        row.arrive_time += chrono::Duration::milliseconds(late_ms);
+        row.receive_time += chrono::Duration::milliseconds(late_ms);
+        row.block_propagation_time += late_ms;
+        row
+    }
+
+    #[test]
+    fn drift_between_two_blocks() {
+        let previous = late("node-0", 1, 0);
+        let row = late("node-0", 11, 30);
+        assert_eq!(
+            drift(
+                (row.arrive_time, row.block_slot_time),
+                (previous.arrive_time, previous.block_slot_time)
+            ),
+            30
+        );
+    }
+
+    #[test]
+    fn fill_leaves_the_oldest_row_empty() {
+        // newest first, as the chain is walked.
+        let mut rows = vec![
+            late("node-0", 3, 25),
+            late("node-0", 2, 10),
+            late("node-0", 1, 0),
+        ];
+        fill(&mut rows);
+        let drifts: Vec<_> = rows.iter().map(|row| row.slot_wall_drift).collect();
+        assert_eq!(drifts, [Some(15), Some(10), None]);
+    }
+
+    #[test]
+    fn report_flags_drifting_and_offset_clocks() {
+        let mut rows = vec![];
+        for (node, late_ms) in [
+            ("node-0", [0, 0]),
+            ("node-1", [500, 500]),
+            ("node-2", [0, 3600]),
+        ] {
+            let mut node_rows = vec![late(node, 3600, late_ms[1]), late(node, 0, late_ms[0])];
+            fill(&mut node_rows);
+            rows.extend(node_rows);
+        }
+        let report = report(&rows, 100);
+        let nodes: Vec<_> = report
+            .iter()
+            .map(|row| {
+                (
+                    row.node.as_str(),
+                    row.clock_offset,
+                    row.total_drift,
+                    row.drift_per_hour,
+                    row.flagged,
+                )
+            })
+            .collect();
+        assert_eq!(
+            nodes,
+            [
+                ("node-0", 0, 0, Some(0.0), false),
+                ("node-1", 500, 0, Some(0.0), true),
+                ("node-2", 0, 3600, Some(3600.0), true),
+            ]
+        );
+        assert!(report[0].warning().is_none());
+        assert!(report[2].warning().unwrap().contains("drifted 3600 ms"));
+    }
+
+    #[test]
+    fn negative_propagation_times_are_counted() {
+        let rows = vec![late("node-0", 2, -150), late("node-0", 1, 0)];
+        let report = report(&rows, 100);
+        assert_eq!(report[0].negative_propagation_times, 1);
+        assert_eq!(report[0].min_propagation_time, -50);
+    }
+}
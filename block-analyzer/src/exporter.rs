@@ -0,0 +1,290 @@
+use crate::Row;
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use concordium_rust_sdk::types::hashes::BlockHash;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// The seconds of slot time the transactions per second are measured over.
+const TPS_WINDOW_SECS: i64 = 60;
+
+/// The latest block of a node.
+struct NodeMetrics {
+    height: u64,
+    execution_time: i64,
+    propagation_time: i64,
+    transactions: u64,
+    /// The number of blocks seen since the start.
+    blocks: u64,
+}
+
+/// A gauge with a value of the latest block of every node.
+struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    value: fn(&NodeMetrics) -> i64,
+}
+
+const GAUGES: &[Gauge] = &[
+    Gauge {
+        name: "block_analyzer_best_block_height",
+        help: "Height of the latest best block of the node.",
+        value: |node| node.height as i64,
+    },
+    Gauge {
+        name: "block_analyzer_execution_time_millis",
+        help: "Execution time of the latest best block of the node in milliseconds.",
+        value: |node| node.execution_time,
+    },
+    Gauge {
+        name: "block_analyzer_propagation_time_millis",
+        help: "Time from the slot of the latest best block of the node until it arrived in milliseconds.",
+        value: |node| node.propagation_time,
+    },
+    Gauge {
+        name: "block_analyzer_block_transactions",
+        help: "Transactions in the latest best block of the node.",
+        value: |node| node.transactions as i64,
+    },
+];
+
+#[derive(Default)]
+struct State {
+    nodes: BTreeMap<String, NodeMetrics>,
+    /// The slot time and transactions of the blocks of the last
+    /// `TPS_WINDOW_SECS`, once per block however many nodes have it.
+    recent: HashMap<BlockHash, (DateTime<Utc>, u64)>,
+}
+
+/// The metrics of the latest blocks of every followed node.
+#[derive(Clone, Default)]
+pub(crate) struct LiveMetrics {
+    state: Arc<Mutex<State>>,
+}
+
+impl LiveMetrics {
+    /// Update the metrics with a new block of a node.
+    pub(crate) fn record(&self, row: &Row) {
+        let mut state = self.state.lock().unwrap();
+        let node = state.nodes.entry(row.node.clone()).or_insert(NodeMetrics {
+            height: 0,
+            execution_time: 0,
+            propagation_time: 0,
+            transactions: 0,
+            blocks: 0,
+        });
+        node.height = row.block_height.height;
+        node.execution_time = row.execution_time;
+        node.propagation_time = row.block_propagation_time;
+        node.transactions = row.tx_count;
+        node.blocks += 1;
+
+        state
+            .recent
+            .entry(row.block_hash)
+            .or_insert((row.block_slot_time, row.tx_count));
+        let newest = state.recent.values().map(|(time, _)| *time).max();
+        if let Some(newest) = newest {
+            let start = newest - Duration::seconds(TPS_WINDOW_SECS);
+            state.recent.retain(|_, (time, _)| *time > start);
+        }
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut text = String::new();
+        // writing to a string cannot fail.
+        for gauge in GAUGES {
+            let _ = writeln!(text, "# HELP {} {}", gauge.name, gauge.help);
+            let _ = writeln!(text, "# TYPE {} gauge", gauge.name);
+            for (uri, node) in &state.nodes {
+                let _ = writeln!(
+                    text,
+                    "{}{{node=\"{}\"}} {}",
+                    gauge.name,
+                    escape(uri),
+                    (gauge.value)(node)
+                );
+            }
+        }
+        let _ = writeln!(
+            text,
+            "# HELP block_analyzer_blocks_total Best blocks of the node seen since the start."
+        );
+        let _ = writeln!(text, "# TYPE block_analyzer_blocks_total counter");
+        for (uri, node) in &state.nodes {
+            let _ = writeln!(
+                text,
+                "block_analyzer_blocks_total{{node=\"{}\"}} {}",
+                escape(uri),
+                node.blocks
+            );
+        }
+        let transactions: u64 = state.recent.values().map(|(_, count)| count).sum();
+        let _ = writeln!(
+            text,
+            "# HELP block_analyzer_tps Transactions per second in the blocks of the last {} seconds of slot time.",
+            TPS_WINDOW_SECS
+        );
+        let _ = writeln!(text, "# TYPE block_analyzer_tps gauge");
+        let _ = writeln!(
+            text,
+            "block_analyzer_tps {}",
+            transactions as f64 / TPS_WINDOW_SECS as f64
+        );
+        text
+    }
+}
+
+/// Escape a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn respond(metrics: &LiveMetrics, request: &Request<Body>) -> Response<Body> {
+    if request.method() != Method::GET || request.uri().path() != "/metrics" {
+        let mut response = Response::new(Body::from("Not found, the metrics are at /metrics\n"));
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return response;
+    }
+    let mut response = Response::new(Body::from(metrics.render()));
+    response.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static("text/plain; version=0.0.4"),
+    );
+    response
+}
+
+/// An HTTP server answering `/metrics` with the `LiveMetrics`.
+pub(crate) struct MetricsServer {
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<hyper::Result<()>>,
+}
+
+impl MetricsServer {
+    /// Serve the `metrics` on all interfaces on `port`.
+    pub(crate) fn start(port: u16, metrics: LiveMetrics) -> anyhow::Result<MetricsServer> {
+        let addr = SocketAddr::from(([0, 0, 0, 0], port));
+        let builder = hyper::Server::try_bind(&addr)
+            .context(format!("cannot listen for metrics on port {}", port))?;
+        let make_service = make_service_fn(move |_| {
+            let metrics = metrics.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let response = respond(&metrics, &request);
+                    async move { Ok::<_, Infallible>(response) }
+                }))
+            }
+        });
+        let (shutdown, stopped) = oneshot::channel();
+        let server = builder.serve(make_service).with_graceful_shutdown(async {
+            let _ = stopped.await;
+        });
+        eprintln!("Serving the metrics on http://{}/metrics", addr);
+        Ok(MetricsServer {
+            shutdown,
+            task: tokio::spawn(server),
+        })
+    }
+
+    /// Stop accepting connections and wait until the open ones are answered.
+    pub(crate) async fn stop(self) -> anyhow::Result<()> {
+        let _ = self.shutdown.send(());
+        self.task.await?.context("the metrics server failed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::row;
+
+    /// The line of the metric `name`, with its labels, in the `text`.
+    fn line<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+        text.lines().find(|line| line.starts_with(name))
+    }
+
+    #[test]
+    fn gauges_show_the_latest_block_of_every_node() {
+        let metrics = LiveMetrics::default();
+        metrics.record(&row("node-0", 1));
+        let mut latest = row("node-0", 2);
+        latest.tx_count = 4;
+        latest.execution_time = 70;
+        metrics.record(&latest);
+        metrics.record(&row("node-1", 1));
+        let text = metrics.render();
+        for (name, value) in [
+            ("block_analyzer_best_block_height{node=\"node-0\"}", "2"),
+            ("block_analyzer_best_block_height{node=\"node-1\"}", "1"),
+            (
+                "block_analyzer_execution_time_millis{node=\"node-0\"}",
+                "70",
+            ),
+            (
+                "block_analyzer_propagation_time_millis{node=\"node-0\"}",
+                "100",
+            ),
+            ("block_analyzer_block_transactions{node=\"node-0\"}", "4"),
+            ("block_analyzer_blocks_total{node=\"node-0\"}", "2"),
+            ("block_analyzer_blocks_total{node=\"node-1\"}", "1"),
+        ] {
+            assert_eq!(
+                line(&text, name),
+                Some(format!("{} {}", name, value).as_str())
+            );
+        }
+    }
+
+    #[test]
+    fn blocks_of_several_nodes_count_once_for_the_tps() {
+        let metrics = LiveMetrics::default();
+        for node in ["node-0", "node-1", "node-2"] {
+            let mut block = row(node, 1);
+            block.tx_count = 30;
+            metrics.record(&block);
+        }
+        assert_eq!(
+            line(&metrics.render(), "block_analyzer_tps "),
+            Some("block_analyzer_tps 0.5")
+        );
+    }
+
+    #[test]
+    fn blocks_before_the_window_are_evicted() {
+        let metrics = LiveMetrics::default();
+        // the slot times are 1, 30 and 61 seconds, the window starts at 1.
+        for height in [1, 30, 61] {
+            let mut block = row("node-0", height);
+            block.tx_count = 6;
+            metrics.record(&block);
+        }
+        assert_eq!(metrics.state.lock().unwrap().recent.len(), 2);
+        assert_eq!(
+            line(&metrics.render(), "block_analyzer_tps "),
+            Some("block_analyzer_tps 0.2")
+        );
+    }
+
+    #[test]
+    fn node_labels_are_escaped() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+        let metrics = LiveMetrics::default();
+        metrics.record(&row("http://\"node\"", 1));
+        assert_eq!(
+            line(&metrics.render(), "block_analyzer_blocks_total{"),
+            Some("block_analyzer_blocks_total{node=\"http://\\\"node\\\"\"} 1")
+        );
+    }
+}
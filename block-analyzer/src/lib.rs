@@ -0,0 +1,1232 @@
+use anomaly::SlowBlocks;
+use anyhow::Context;
+use checkpoint::{Checkpoint, Reached, Scanned};
+use chrono::{DateTime, Utc};
+use concordium_rust_sdk::{
+    common::SerdeSerialize,
+    endpoints,
+    types::{self, hashes::BlockHash, AbsoluteBlockHeight, Slot},
+};
+use exporter::{LiveMetrics, MetricsServer};
+use futures::StreamExt;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use node_client::{connect_with_retry, NodeEndpoint};
+use output_sink::{Format, OutputSink};
+use sqlite::Database;
+use std::collections::HashMap;
+use std::time::Duration;
+use structopt::StructOpt;
+use tonic::transport::Certificate;
+
+mod anomaly;
+mod bakers;
+mod checkpoint;
+mod drift;
+mod exporter;
+mod sqlite;
+mod summary;
+mod tps;
+
+#[derive(StructOpt)]
+pub struct App {
+    #[structopt(
+        long = "nodes",
+        help = "GRPC interface of the node.",
+        use_delimiter = true,
+        default_value = "http://localhost:7000,http://localhost:7001,http://localhost:7002,http://localhost:7003,http://localhost:7004"
+    )]
+    endpoints: Vec<tonic::transport::Endpoint>,
+    #[structopt(long = "block", help = "hash of the block to start with")]
+    start_block: Option<types::hashes::BlockHash>,
+    #[structopt(
+        long = "from-height",
+        help = "Start with the block at this height instead of the best block.",
+        conflicts_with = "start-block"
+    )]
+    from_height: Option<u64>,
+    #[structopt(
+        long = "out",
+        help = "File to output the measurements to. If omitted they are written to stdout."
+    )]
+    out: Option<std::path::PathBuf>,
+    #[structopt(
+        long = "format",
+        help = "The format of the measurements.",
+        possible_values = &Format::variants(),
+        case_insensitive = true,
+        default_value = "csv"
+    )]
+    format: Format,
+    #[structopt(
+        long = "include-empty-blocks",
+        help = "Whether if empty blocks should be included in the batch"
+    )]
+    include_empty_blocks: bool,
+    #[structopt(
+        long = "max-concurrency",
+        help = "The maximal number of nodes that are queried at the same time. Defaults to all nodes."
+    )]
+    max_concurrency: Option<usize>,
+    #[structopt(
+        long = "dedup-blocks",
+        help = "Write every block once with the timings of the first node that has it, instead of once per node."
+    )]
+    dedup_blocks: bool,
+    #[structopt(
+        long = "average-timings",
+        help = "With --dedup-blocks, average the execution and propagation times over all nodes that have the block. The number of nodes is in the \"Averaged nodes\" column, the other timings are the ones of the first node.",
+        requires = "dedup-blocks"
+    )]
+    average_timings: bool,
+    #[structopt(
+        long = "max-blocks",
+        help = "Stop after scanning this many blocks of each node."
+    )]
+    max_blocks: Option<u64>,
+    #[structopt(
+        long = "to-height",
+        help = "Stop at the block with this height instead of walking back to genesis."
+    )]
+    to_height: Option<u64>,
+    #[structopt(
+        long = "max-retries",
+        help = "How often a failed query is retried before the node is skipped.",
+        default_value = "5"
+    )]
+    max_retries: u32,
+    #[structopt(
+        long = "admin-token",
+        help = "The token for accessing the gRPC interface of the nodes.",
+        default_value = node_client::DEFAULT_TOKEN
+    )]
+    admin_token: String,
+    #[structopt(
+        long = "ca-cert",
+        help = "PEM file with the certificate authority of the https endpoints, for nodes with self-signed certificates. Defaults to the system roots.",
+        parse(from_os_str)
+    )]
+    ca_cert: Option<std::path::PathBuf>,
+    #[structopt(
+        long = "tx-breakdown",
+        help = "Count the transactions of each block by kind, such as transfers and contract transactions. This costs an extra query per block."
+    )]
+    tx_breakdown: bool,
+    #[structopt(
+        long = "summary-size",
+        help = "Fill in the size of the block summary sent by the node. This costs an extra query per block."
+    )]
+    summary_size: bool,
+    #[structopt(
+        long = "finalization-delay",
+        help = "Fill in an estimate of how long after its arrival every block was finalized, from the arrival of the block that contains its finalization record. The nodes do not report when they finalized a block. Not available with --follow. This costs an extra query per scanned block, including the empty ones.",
+        conflicts_with = "follow"
+    )]
+    finalization_delay: bool,
+    #[structopt(
+        long = "summary-only",
+        help = "Only print the statistics of the timings per node instead of the rows.",
+        conflicts_with = "follow"
+    )]
+    summary_only: bool,
+    #[structopt(
+        long = "tps-window-secs",
+        help = "Also report the transactions per second in windows of this many seconds of slot time. Written next to --out, e.g. foo-tps.csv, or to stderr.",
+        conflicts_with_all = &["follow", "summary-only"]
+    )]
+    tps_window_secs: Option<u64>,
+    #[structopt(
+        long = "by-baker",
+        help = "Also report the number of blocks and the mean execution and propagation time of the blocks of every baker. Written next to --out, e.g. foo-bakers.csv, or to stderr.",
+        conflicts_with_all = &["follow", "summary-only"]
+    )]
+    by_baker: bool,
+    #[structopt(
+        long = "drift-report",
+        help = "Also report per node how far its clock is off from the slot times, which makes its propagation times off too. Written next to --out, e.g. foo-drift.csv, or to stderr.",
+        conflicts_with_all = &["follow", "summary-only"]
+    )]
+    drift_report: bool,
+    #[structopt(
+        long = "max-drift-ms",
+        help = "Flag the nodes in the drift report whose clock drifted or is behind the fastest node by more than this many milliseconds.",
+        default_value = "100"
+    )]
+    max_drift_ms: i64,
+    #[structopt(
+        long = "follow",
+        help = "Keep running and write a row for every new best block of each node until Ctrl-C is pressed, instead of walking the chains once."
+    )]
+    follow: bool,
+    #[structopt(
+        long = "poll-interval",
+        help = "Seconds between the queries for new blocks with --follow.",
+        default_value = "1"
+    )]
+    poll_interval: u64,
+    #[structopt(
+        long = "metrics-port",
+        help = "With --follow, serve the latest height, execution time and TPS of the nodes on http://0.0.0.0:<port>/metrics in the Prometheus format.",
+        requires = "follow"
+    )]
+    metrics_port: Option<u16>,
+    #[structopt(
+        long = "anomaly-window",
+        help = "With --follow, warn about every block whose execution time is above --anomaly-percentile of the last this many blocks of the node, and mark it in the Anomaly column.",
+        requires = "follow"
+    )]
+    anomaly_window: Option<usize>,
+    #[structopt(
+        long = "anomaly-percentile",
+        help = "The percentile of the recent execution times a block must exceed to be an anomaly.",
+        default_value = "95"
+    )]
+    anomaly_percentile: f64,
+    #[structopt(
+        long = "no-progress",
+        help = "Do not show the progress of walking the chains. It is also hidden when stderr is not a terminal."
+    )]
+    no_progress: bool,
+    #[structopt(
+        long = "checkpoint",
+        help = "JSON file with the blocks of every node scanned by previous runs. Only the blocks that are not in it are scanned, and it is updated with them once the rows are written. A node whose chain no longer has the tip of the previous run, e.g. after a reorg, is scanned again.",
+        conflicts_with_all = &["follow", "summary-only"]
+    )]
+    checkpoint: Option<std::path::PathBuf>,
+    #[structopt(
+        long = "sqlite",
+        help = "Also insert the rows into the blocks table of this SQLite database, which is created if needed. Every run appends its rows with its --run-id.",
+        conflicts_with = "summary-only",
+        parse(from_os_str)
+    )]
+    sqlite: Option<std::path::PathBuf>,
+    #[structopt(
+        long = "run-id",
+        help = "The run_id of the rows inserted with --sqlite, to tell the runs apart. Defaults to the start time of the run.",
+        requires = "sqlite"
+    )]
+    run_id: Option<String>,
+}
+
+impl App {
+    /// Write the rows to `out` and in `format` instead, if they are given.
+    pub fn with_output(
+        mut self,
+        out: Option<std::path::PathBuf>,
+        format: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        if let Some(out) = out {
+            self.out = Some(out);
+        }
+        if let Some(format) = format {
+            self.format = format.parse().map_err(anyhow::Error::msg)?;
+        }
+        Ok(self)
+    }
+}
+
+/// Print a notice of a retried connection or query.
+fn warn(message: &str) {
+    eprintln!("{}", message);
+}
+
+/// How far the chain of each node is walked.
+#[derive(Clone, Copy)]
+struct Traversal {
+    /// The block to start from instead of the best block.
+    start_block: Option<BlockHash>,
+    /// The height of the block to start from instead of the best block.
+    from_height: Option<u64>,
+    include_empty_blocks: bool,
+    max_blocks: Option<u64>,
+    /// The lowest block height that is scanned.
+    to_height: Option<u64>,
+    max_retries: u32,
+    /// Whether the transactions of every block are counted by kind.
+    tx_breakdown: bool,
+    summary_size: bool,
+    finalization_delay: bool,
+}
+
+#[derive(SerdeSerialize)]
+struct Row {
+    #[serde(rename = "Node id")]
+    node: String,
+    #[serde(rename = "Block height")]
+    block_height: AbsoluteBlockHeight,
+    #[serde(rename = "Block hash")]
+    block_hash: BlockHash,
+    #[serde(rename = "Receive time")]
+    receive_time: DateTime<Utc>,
+    #[serde(rename = "Arrive time")]
+    arrive_time: DateTime<Utc>,
+    #[serde(rename = "Transaction count")]
+    tx_count: u64,
+    #[serde(rename = "Block execution time (millis)")]
+    execution_time: i64,
+    #[serde(rename = "Block slot")]
+    block_slot: Slot,
+    #[serde(rename = "Block slot time")]
+    block_slot_time: DateTime<Utc>,
+    #[serde(rename = "Block propagation time (millis)")]
+    block_propagation_time: i64,
+    /// The baker that produced the block, empty for genesis.
+    #[serde(rename = "Block baker id")]
+    block_baker_id: Option<types::BakerId>,
+    /// How far the clock of the node moved away from the slot times since the
+    /// previous block of the node in the rows. Empty for the first block.
+    #[serde(rename = "Slot wall drift (millis)")]
+    slot_wall_drift: Option<i64>,
+    /// Whether the execution time is above the percentile of the recent
+    /// blocks, only with --anomaly-window.
+    #[serde(rename = "Anomaly")]
+    anomaly: Option<bool>,
+    #[serde(rename = "Baker")]
+    is_baker: bool,
+    #[serde(rename = "Finalizer")]
+    is_finalizer: bool,
+    #[serde(rename = "Finalized")]
+    finalized: bool,
+    /// Empty without --finalization-delay, if the block is not finalized or if
+    /// the record finalizing it is not in the scanned part of the chain. Only
+    /// an estimate, see `analyze_node`.
+    #[serde(rename = "Estimated finalization delay (millis)")]
+    finalization_delay: Option<i64>,
+    /// The size of the JSON of the block summary as sent by the node, which
+    /// grows with the transactions of the block and their events. Empty if the
+    /// summary was not queried or the node does not serve it.
+    #[serde(rename = "Block summary size (bytes)")]
+    summary_size_bytes: Option<u64>,
+    // the number of transactions of each kind, only with --tx-breakdown.
+    #[serde(rename = "Transfers")]
+    transfers: Option<u64>,
+    #[serde(rename = "Contract transactions")]
+    contract_transactions: Option<u64>,
+    #[serde(rename = "Baker and delegation transactions")]
+    staking_transactions: Option<u64>,
+    #[serde(rename = "Other account transactions")]
+    other_transactions: Option<u64>,
+    #[serde(rename = "Rejected transactions")]
+    rejected_transactions: Option<u64>,
+    #[serde(rename = "Account creations")]
+    account_creations: Option<u64>,
+    #[serde(rename = "Chain updates")]
+    chain_updates: Option<u64>,
+    /// The number of nodes the execution and propagation times are averaged
+    /// over, only with --average-timings. The other timings are the ones of
+    /// the node in the node column.
+    #[serde(rename = "Averaged nodes")]
+    averaged_nodes: Option<i64>,
+}
+
+/// Walk the chain of every configured node and collect the timings of each block.
+pub async fn run(app: App) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        app.tps_window_secs != Some(0),
+        "--tps-window-secs must be at least 1"
+    );
+    anyhow::ensure!(
+        app.anomaly_window != Some(0),
+        "--anomaly-window must be at least 1"
+    );
+    anyhow::ensure!(
+        app.anomaly_percentile > 0.0 && app.anomaly_percentile <= 100.0,
+        "--anomaly-percentile must be above 0 and at most 100"
+    );
+    let max_concurrency = app.max_concurrency.unwrap_or(app.endpoints.len()).max(1);
+
+    let ca_cert = match &app.ca_cert {
+        Some(path) => Some(Certificate::from_pem(
+            std::fs::read(path)
+                .context(format!("cannot read the CA certificate {}", path.display()))?,
+        )),
+        None => None,
+    };
+    let endpoints = app
+        .endpoints
+        .into_iter()
+        .map(|endpoint| NodeEndpoint::new(endpoint, &app.admin_token, ca_cert.as_ref()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let run_id = app
+        .run_id
+        .unwrap_or_else(|| Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string());
+    let mut database = app
+        .sqlite
+        .as_deref()
+        .map(|path| Database::open(path, run_id))
+        .transpose()?;
+    if let (Some(path), Some(database)) = (&app.sqlite, &database) {
+        eprintln!(
+            "Inserting the rows into {} as run {}",
+            path.display(),
+            database.run_id()
+        );
+    }
+
+    let traversal = Traversal {
+        start_block: app.start_block,
+        from_height: app.from_height,
+        include_empty_blocks: app.include_empty_blocks,
+        max_blocks: app.max_blocks,
+        to_height: app.to_height,
+        max_retries: app.max_retries,
+        tx_breakdown: app.tx_breakdown,
+        summary_size: app.summary_size,
+        finalization_delay: app.finalization_delay,
+    };
+    if app.follow {
+        let poll_interval = Duration::from_secs(app.poll_interval);
+        return follow(
+            endpoints,
+            traversal,
+            poll_interval,
+            app.metrics_port,
+            app.anomaly_window
+                .map(|window| SlowBlocks::new(window, app.anomaly_percentile)),
+            OutputSink::open(app.out.as_deref(), app.format)?.streaming()?,
+            database,
+        )
+        .await;
+    }
+    let node_uris: Vec<String> = endpoints.iter().map(NodeEndpoint::uri).collect();
+    let node_uris = &node_uris;
+    let mut checkpoint = app
+        .checkpoint
+        .as_deref()
+        .map(Checkpoint::load)
+        .transpose()?;
+    let scanned_before: Vec<Option<Scanned>> = node_uris
+        .iter()
+        .map(|uri| {
+            checkpoint
+                .as_ref()
+                .and_then(|checkpoint| checkpoint.get(uri))
+        })
+        .collect();
+    let scanned_before = &scanned_before;
+    // a bar per node, which draws itself only if stderr is a terminal.
+    let progress = if app.no_progress {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    } else {
+        MultiProgress::new()
+    };
+    let progress = &progress;
+    let style =
+        &ProgressStyle::with_template("{prefix} [{bar:40}] {pos}/{len} blocks, {eta} left")?
+            .progress_chars("=> ");
+    // a node that keeps failing is skipped so the other nodes still produce rows.
+    let mut node_rows: Vec<(usize, Scan, Vec<Row>)> =
+        futures::stream::iter(endpoints.into_iter().enumerate())
+            .map(|(node_idx, endpoint)| async move {
+                let bar = progress.add(
+                    ProgressBar::new(0)
+                        .with_style(style.clone())
+                        .with_prefix(node_uris[node_idx].clone()),
+                );
+                let result =
+                    analyze_node(endpoint, traversal, scanned_before[node_idx], &bar).await;
+                bar.finish_and_clear();
+                match result {
+                    Ok((scan, rows)) => Some((node_idx, scan, rows)),
+                    Err(e) => {
+                        progress.suspend(|| eprintln!("Skipping {}: {:#}", node_uris[node_idx], e));
+                        None
+                    }
+                }
+            })
+            .buffer_unordered(max_concurrency)
+            .filter_map(futures::future::ready)
+            .collect()
+            .await;
+    // the nodes finish in any order, so restore the order they were given in.
+    node_rows.sort_by_key(|(node_idx, _, _)| *node_idx);
+    for (node_idx, scan, _) in &node_rows {
+        match scan.pruned_below {
+            Some(height) => eprintln!(
+                "Scanned {} blocks of {}, down to height {} below which the node is pruned",
+                scan.blocks, node_uris[*node_idx], height
+            ),
+            None => eprintln!("Scanned {} blocks of {}", scan.blocks, node_uris[*node_idx]),
+        }
+        if let (Some(checkpoint), Some(scanned)) = (&mut checkpoint, scan.scanned) {
+            checkpoint.update(&node_uris[*node_idx], scanned);
+        }
+    }
+    for (_, _, rows) in &mut node_rows {
+        drift::fill(rows);
+    }
+    let mut csv_rows: Vec<Row> = node_rows
+        .into_iter()
+        .flat_map(|(_, _, rows)| rows)
+        .collect();
+    // the drift of a node is measured on all its rows, before the duplicates
+    // are dropped.
+    let drift_rows = app
+        .drift_report
+        .then(|| drift::report(&csv_rows, app.max_drift_ms));
+    if app.dedup_blocks {
+        csv_rows = dedup_blocks(csv_rows, app.average_timings);
+    }
+
+    csv_rows.reverse();
+    if app.summary_only {
+        return summary::write_summary(&csv_rows, &mut std::io::stdout());
+    }
+    // --out is only opened, and truncated, on the path that writes the rows.
+    let mut out = OutputSink::open(app.out.as_deref(), app.format)?;
+    for row in &csv_rows {
+        out.write(row)?;
+    }
+    out.finish()?;
+    if let Some(database) = &mut database {
+        database.insert(&csv_rows)?;
+    }
+    // the checkpoint only moves on once the rows are written.
+    if let Some(checkpoint) = &checkpoint {
+        checkpoint.save()?;
+    }
+    if let Some(window_secs) = app.tps_window_secs {
+        write_tps(app.out.as_deref(), app.format, &csv_rows, window_secs)?;
+    }
+    if app.by_baker {
+        write_bakers(app.out.as_deref(), app.format, &csv_rows)?;
+    }
+    if let Some(drift_rows) = drift_rows {
+        write_drift(app.out.as_deref(), app.format, drift_rows)?;
+    }
+    // the rows may be written to stdout, so the summary goes to stderr.
+    summary::write_summary(&csv_rows, &mut std::io::stderr())
+}
+
+/// Open the output of a report next to `out` with `suffix` appended to its
+/// stem. Without `out` the rows go to stdout, so the report goes to stderr to
+/// keep them readable as csv or JSON.
+fn open_report(
+    out: Option<&std::path::Path>,
+    suffix: &str,
+    format: Format,
+) -> anyhow::Result<OutputSink> {
+    match out {
+        Some(out) => OutputSink::open(Some(&output_sink::with_suffix(out, suffix)), format),
+        None => Ok(OutputSink::stderr(format)),
+    }
+}
+
+/// Write the transactions per second of the `rows` in windows of
+/// `window_secs` seconds next to `out`, or to stderr if there is no `out`.
+fn write_tps(
+    out: Option<&std::path::Path>,
+    format: Format,
+    rows: &[Row],
+    window_secs: u64,
+) -> anyhow::Result<()> {
+    let mut tps_out = open_report(out, "-tps", format)?;
+    for row in tps::windows(rows, window_secs as i64) {
+        tps_out.write(row)?;
+    }
+    tps_out.finish()
+}
+
+/// Write the timings of the blocks of every baker in the `rows` next to `out`,
+/// or to stderr if there is no `out`.
+fn write_bakers(out: Option<&std::path::Path>, format: Format, rows: &[Row]) -> anyhow::Result<()> {
+    let mut bakers_out = open_report(out, "-bakers", format)?;
+    for row in bakers::by_baker(rows) {
+        bakers_out.write(row)?;
+    }
+    bakers_out.finish()
+}
+
+/// Write the drift report next to `out`, or to stderr if there is no `out`,
+/// and warn about the flagged nodes.
+fn write_drift(
+    out: Option<&std::path::Path>,
+    format: Format,
+    rows: Vec<drift::DriftRow>,
+) -> anyhow::Result<()> {
+    let mut drift_out = open_report(out, "-drift", format)?;
+    for row in &rows {
+        drift_out.write(row)?;
+    }
+    drift_out.finish()?;
+    for warning in rows.iter().filter_map(|row| row.warning()) {
+        eprintln!("{}", warning);
+    }
+    Ok(())
+}
+
+/// Keep one row per block, the one of the first node that has the block. With
+/// `average` the execution and propagation times are instead averaged over all
+/// nodes and the number of nodes is in its own column. The other timings stay
+/// the ones of the first node, which stays in the node column.
+fn dedup_blocks(rows: Vec<Row>, average: bool) -> Vec<Row> {
+    let mut deduped: Vec<Row> = vec![];
+    // the position of each block in `deduped` and the number of nodes that have it.
+    let mut blocks: HashMap<BlockHash, (usize, i64)> = HashMap::new();
+    for row in rows {
+        match blocks.get_mut(&row.block_hash) {
+            Some((pos, count)) => {
+                if average {
+                    let kept = &mut deduped[*pos];
+                    kept.execution_time += row.execution_time;
+                    kept.block_propagation_time += row.block_propagation_time;
+                }
+                *count += 1;
+            }
+            None => {
+                blocks.insert(row.block_hash, (deduped.len(), 1));
+                deduped.push(row);
+            }
+        }
+    }
+    if average {
+        for (pos, count) in blocks.into_values().filter(|(_, count)| *count > 1) {
+            let row = &mut deduped[pos];
+            row.execution_time /= count;
+            row.block_propagation_time /= count;
+            row.averaged_nodes = Some(count);
+        }
+    }
+    deduped
+}
+
+/// Follow the best blocks of all nodes and write a row for every new block
+/// until Ctrl-C is pressed. With a `metrics_port` the latest blocks are also
+/// served as Prometheus metrics. The `slow_blocks`, if any, mark the rows of
+/// slow blocks. Every row is also inserted into the `database`, if any.
+async fn follow(
+    endpoints: Vec<NodeEndpoint>,
+    traversal: Traversal,
+    poll_interval: Duration,
+    metrics_port: Option<u16>,
+    mut slow_blocks: Option<SlowBlocks>,
+    mut out: OutputSink,
+    mut database: Option<Database>,
+) -> anyhow::Result<()> {
+    let metrics = metrics_port.map(|_| LiveMetrics::default());
+    let server = metrics_port
+        .zip(metrics.clone())
+        .map(|(port, metrics)| MetricsServer::start(port, metrics))
+        .transpose()?;
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(100);
+    for endpoint in endpoints {
+        let sender = sender.clone();
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            let node_uri = endpoint.uri();
+            if let Err(e) = follow_node(endpoint, traversal, poll_interval, metrics, sender).await {
+                eprintln!("Stopped following {}: {:#}", node_uri, e);
+            }
+        });
+    }
+    drop(sender);
+
+    let result = loop {
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => {
+                break res.context("cannot listen for Ctrl-C");
+            }
+            row = receiver.recv() => match row {
+                Some(mut row) => {
+                    if let Some(warning) = slow_blocks.as_mut().and_then(|slow| slow.check(&mut row)) {
+                        eprintln!("{}", warning);
+                    }
+                    // stop the metrics server below also if a row cannot be written.
+                    if let Err(e) = out.write(&row) {
+                        break Err(e);
+                    }
+                    if let Some(database) = &mut database {
+                        if let Err(e) = database.insert(std::slice::from_ref(&row)) {
+                            break Err(e);
+                        }
+                    }
+                }
+                // every node has failed.
+                None => break Ok(()),
+            }
+        }
+    };
+    if let Some(server) = server {
+        server.stop().await?;
+    }
+    result
+}
+
+/// Poll the best block of the node at `endpoint` and send a row to `sender`
+/// for every block added since the previous poll, oldest first. After a
+/// reorg, these are all blocks of the new branch above the fork. Every block,
+/// also an empty one, is recorded in the `metrics`.
+async fn follow_node(
+    endpoint: NodeEndpoint,
+    traversal: Traversal,
+    poll_interval: Duration,
+    metrics: Option<LiveMetrics>,
+    sender: tokio::sync::mpsc::Sender<Row>,
+) -> anyhow::Result<()> {
+    let node_uri = endpoint.uri();
+    let mut client = connect_with_retry(
+        &endpoint,
+        traversal.max_retries,
+        node_client::Client::connect,
+        &warn,
+    )
+    .await?;
+    let retries = traversal.max_retries;
+
+    let ni = retry(&mut client, &endpoint, retries, |mut client| async move {
+        client.sdk().node_info().await
+    })
+    .await?;
+    let (is_baker, is_finalizer) = baker_status(ni.peer_details);
+
+    let consensus_info = retry(&mut client, &endpoint, retries, |mut client| async move {
+        client.get_consensus_status().await
+    })
+    .await?;
+    let mut last_seen = consensus_info.best_block;
+    let mut finalized_height = consensus_info.last_finalized_block_height.height;
+    // the heights of the blocks on the chain above the last finalized block,
+    // which are either written or were there before the start.
+    let mut known: HashMap<BlockHash, u64> = HashMap::new();
+    let mut cb = consensus_info.best_block;
+    loop {
+        let bi = retry(&mut client, &endpoint, retries, |mut client| async move {
+            client.get_block_info(&cb).await
+        })
+        .await?
+        .context(format!("{} does not have block {}", node_uri, cb))?;
+        if bi.block_height.height <= finalized_height {
+            break;
+        }
+        known.insert(bi.block_hash, bi.block_height.height);
+        cb = bi.block_parent;
+    }
+    // the arrive and slot time of the previous new block, for the drift.
+    let mut previous = None;
+
+    let mut interval = tokio::time::interval(poll_interval);
+    loop {
+        interval.tick().await;
+        let consensus_info = retry(&mut client, &endpoint, retries, |mut client| async move {
+            client.get_consensus_status().await
+        })
+        .await?;
+        if consensus_info.best_block == last_seen {
+            continue;
+        }
+        // walk back to a known block, the previous best block or the one a new
+        // branch forked off, or to the last finalized block, so every block of
+        // a new branch is written.
+        let mut new_blocks = vec![];
+        let mut cb = consensus_info.best_block;
+        while !known.contains_key(&cb) {
+            let bi = retry(&mut client, &endpoint, retries, |mut client| async move {
+                client.get_block_info(&cb).await
+            })
+            .await?
+            .context(format!("{} does not have block {}", node_uri, cb))?;
+            if bi.block_height.height <= finalized_height {
+                break;
+            }
+            known.insert(bi.block_hash, bi.block_height.height);
+            cb = bi.block_parent;
+            new_blocks.push(bi);
+        }
+        for bi in new_blocks.iter().rev() {
+            // new best blocks are not finalized yet.
+            let mut row = block_row(&node_uri, bi, is_baker, is_finalizer, None);
+            let times = (row.arrive_time, row.block_slot_time);
+            row.slot_wall_drift = previous.map(|previous| drift::drift(times, previous));
+            previous = Some(times);
+            if let Some(metrics) = &metrics {
+                metrics.record(&row);
+            }
+            if bi.transaction_count == 0 && !traversal.include_empty_blocks {
+                continue;
+            }
+            if traversal.tx_breakdown || traversal.summary_size {
+                let summary = block_summary(&mut client, &endpoint, retries, bi.block_hash).await?;
+                if let Some(Summary { summary, size }) = summary {
+                    if traversal.tx_breakdown {
+                        count_transactions(&mut row, &summary);
+                    }
+                    if traversal.summary_size {
+                        row.summary_size_bytes = Some(size);
+                    }
+                }
+            }
+            if sender.send(row).await.is_err() {
+                return Ok(());
+            }
+        }
+        last_seen = consensus_info.best_block;
+        finalized_height = consensus_info.last_finalized_block_height.height;
+        known.retain(|_, height| *height > finalized_height);
+    }
+}
+
+/// How far the chain of a node was walked.
+struct Scan {
+    /// The number of scanned blocks.
+    blocks: u64,
+    /// The lowest height the node still has, if the walk stopped there
+    /// because the node pruned the blocks below.
+    pruned_below: Option<u64>,
+    /// The blocks scanned by this and the previous runs, for the checkpoint.
+    /// `None` if the walk did not reach the blocks of the previous runs.
+    scanned: Option<Scanned>,
+}
+
+/// Walk the chain of the node at `endpoint` from the start block, or the best
+/// block, back to genesis or the limits of the `traversal` and collect the
+/// timings of its blocks. A pruned node is walked until the first block it no
+/// longer has. The blocks `scanned_before` by a previous run are skipped if
+/// the walk reaches their tip, otherwise they are scanned again.
+/// Returns how far the chain was walked with the rows. The scanned blocks are
+/// counted on `progress`.
+async fn analyze_node(
+    endpoint: NodeEndpoint,
+    traversal: Traversal,
+    scanned_before: Option<Scanned>,
+    progress: &ProgressBar,
+) -> anyhow::Result<(Scan, Vec<Row>)> {
+    let node_uri = endpoint.uri();
+    let mut csv_rows = vec![];
+    let mut client = connect_with_retry(
+        &endpoint,
+        traversal.max_retries,
+        node_client::Client::connect,
+        &warn,
+    )
+    .await?;
+    let retries = traversal.max_retries;
+
+    let version = retry(&mut client, &endpoint, retries, |mut client| async move {
+        client.sdk().version().await
+    })
+    .await?;
+    progress.suspend(|| eprintln!("Version: {}", version));
+    let peers = retry(&mut client, &endpoint, retries, |mut client| async move {
+        client.sdk().peer_list(true).await
+    })
+    .await?;
+    progress.suspend(|| eprintln!("Peers: {:?}", peers));
+
+    let ni = retry(&mut client, &endpoint, retries, |mut client| async move {
+        client.sdk().node_info().await
+    })
+    .await?;
+    progress.suspend(|| eprintln!("Node info: {:?}", ni));
+
+    let consensus_info = retry(&mut client, &endpoint, retries, |mut client| async move {
+        client.get_consensus_status().await
+    })
+    .await?;
+    let gb = consensus_info.genesis_block;
+    let mut cb = match (traversal.start_block, traversal.from_height) {
+        (Some(start_block), _) => start_block,
+        (None, Some(height)) => {
+            let blocks = retry(&mut client, &endpoint, retries, |mut client| async move {
+                let height = endpoints::BlocksAtHeightInput::Absolute {
+                    height: AbsoluteBlockHeight { height },
+                };
+                client.sdk().get_blocks_at_height(height).await
+            })
+            .await?;
+            *blocks
+                .first()
+                .context(format!("{} has no block at height {}", node_uri, height))?
+        }
+        (None, None) => consensus_info.best_block,
+    };
+
+    let (is_baker, is_finalizer) = baker_status(ni.peer_details);
+
+    // a finalization record finalizes the block it points to and its ancestors.
+    // The node reports when it finalized its last finalized block. For the
+    // older records the time of finalization is approximated by the arrival of
+    // the block that contains the record, which is scanned before the
+    // finalized blocks.
+    let mut finalization_times: HashMap<BlockHash, DateTime<Utc>> = HashMap::new();
+    if traversal.finalization_delay {
+        if let Some(time) = consensus_info.last_finalized_time {
+            finalization_times.insert(consensus_info.last_finalized_block, time);
+        }
+    }
+    let mut finalization_time = None;
+    let mut scanned = 0;
+    let mut lowest_height = None;
+    let mut pruned_below = None;
+    // the first block of the walk, and the height of the next one to scan.
+    let mut tip = None;
+    let mut cb_height = None;
+    let mut skip = scanned_before;
+    // the chain is walked down to height 1, genesis is not scanned.
+    let lowest = traversal.to_height.unwrap_or(0).max(1);
+    let set_length = |height: u64, scanned: u64| {
+        let total = scanned + (height + 1).saturating_sub(lowest);
+        progress.set_length(traversal.max_blocks.map_or(total, |max| total.min(max)));
+    };
+    while cb != gb && traversal.max_blocks.is_none_or(|max| scanned < max) {
+        let bi = retry(&mut client, &endpoint, retries, |mut client| async move {
+            client.get_block_info(&cb).await
+        })
+        .await?;
+        let Some(bi) = bi else {
+            // a block the node no longer has is not an error past the first one.
+            let height =
+                lowest_height.context(format!("{} does not have block {}", node_uri, cb))?;
+            progress.suspend(|| {
+                eprintln!(
+                    "{} is pruned, it does not have block {} below height {}",
+                    node_uri, cb, height
+                )
+            });
+            pruned_below = Some(height);
+            break;
+        };
+        tip.get_or_insert((bi.block_hash, bi.block_height.height));
+        cb_height = Some(bi.block_height.height);
+        let height = bi.block_height.height;
+        if let Some(before) = skip {
+            match before.reached(&bi.block_hash, height) {
+                Reached::Above => {}
+                Reached::Tip => {
+                    // the blocks down to `below` were scanned by a previous run.
+                    progress.suspend(|| {
+                        eprintln!(
+                            "Skipping the blocks of {} from height {} down to {} scanned before",
+                            node_uri,
+                            height,
+                            before.below_height + 1
+                        )
+                    });
+                    cb = before.below;
+                    cb_height = Some(before.below_height);
+                    lowest_height = Some(before.below_height + 1);
+                    skip = None;
+                    set_length(before.below_height, scanned);
+                    continue;
+                }
+                Reached::Elsewhere => {
+                    progress.suspend(|| {
+                        eprintln!(
+                            "Block {} of {} at height {} is not on the branch scanned before, its blocks are scanned again",
+                            bi.block_hash, node_uri, height
+                        )
+                    });
+                    skip = None;
+                }
+            }
+        }
+        lowest_height = Some(bi.block_height.height);
+        if traversal
+            .to_height
+            .is_some_and(|to_height| bi.block_height.height < to_height)
+        {
+            break;
+        }
+        if scanned == 0 {
+            set_length(bi.block_height.height, 0);
+        }
+        scanned += 1;
+        progress.inc(1);
+        let produced = bi.transaction_count != 0 || traversal.include_empty_blocks;
+        // the summary is only queried for the columns that need it, but the
+        // finalization records are in the empty blocks too.
+        let summary = if traversal.finalization_delay
+            || (produced && (traversal.tx_breakdown || traversal.summary_size))
+        {
+            block_summary(&mut client, &endpoint, retries, cb).await?
+        } else {
+            None
+        };
+        if traversal.finalization_delay {
+            if let Some(finalization) = summary
+                .as_ref()
+                .and_then(|summary| summary.summary.finalization_data.as_ref())
+            {
+                // the time reported by the node is the more accurate one.
+                finalization_times
+                    .entry(finalization.block_pointer)
+                    .or_insert(bi.block_arrive_time);
+            }
+            if let Some(time) = finalization_times.remove(&bi.block_hash) {
+                finalization_time = Some(time);
+            }
+        }
+        if produced {
+            let mut row = block_row(&node_uri, &bi, is_baker, is_finalizer, finalization_time);
+            if let Some(summary) = &summary {
+                if traversal.tx_breakdown {
+                    count_transactions(&mut row, &summary.summary);
+                }
+                if traversal.summary_size {
+                    row.summary_size_bytes = Some(summary.size);
+                }
+            }
+            progress.suspend(|| {
+                eprintln!("{}", node_uri);
+                eprintln!("{}", row.block_hash);
+                eprintln!("Block receive time: {}", row.receive_time);
+                eprintln!("Block arrive time: {}", row.arrive_time);
+                eprintln!("Block execution time: {}", row.execution_time);
+                eprintln!("Block slot {}", row.block_slot);
+                eprintln!("Block slot time {}", row.block_slot_time);
+                eprintln!("Block propagation time {}", row.block_propagation_time);
+                if let Some(baker) = row.block_baker_id {
+                    eprintln!("Block baker {}", baker);
+                }
+                eprintln!("Consensus status {:?}", consensus_info);
+                eprintln!("Transactions in block: {}", row.tx_count);
+            });
+            csv_rows.push(row);
+        }
+        cb = bi.block_parent;
+        cb_height = Some(bi.block_height.height - 1);
+    }
+    // the blocks of a previous run are only kept if this walk reached them, so
+    // that the scanned blocks stay one range.
+    if scanned_before.is_some() && skip.is_some() {
+        progress.suspend(|| {
+            eprintln!(
+                "The walk of {} did not reach the blocks scanned before, its checkpoint is kept",
+                node_uri
+            )
+        });
+    }
+    let scanned_now = match (tip, cb_height) {
+        (Some((tip, tip_height)), Some(below_height)) if skip.is_none() => Some(Scanned {
+            tip,
+            tip_height,
+            below: cb,
+            below_height,
+        }),
+        _ => None,
+    };
+    let scan = Scan {
+        blocks: scanned,
+        pruned_below,
+        scanned: scanned_now,
+    };
+    Ok((scan, csv_rows))
+}
+
+/// Whether a node with the given details is a baker and a finalizer.
+fn baker_status(peer_details: types::queries::PeerDetails) -> (bool, bool) {
+    match peer_details {
+        types::queries::PeerDetails::Bootstrapper => (false, false),
+        types::queries::PeerDetails::Node { consensus_state } => match consensus_state {
+            types::queries::ConsensusState::NotRunning => (false, false),
+            types::queries::ConsensusState::Passive => (false, false),
+            types::queries::ConsensusState::Active { active_state } => match active_state {
+                types::queries::ActiveConsensusState::NotInCommittee => (false, false),
+                types::queries::ActiveConsensusState::IncorrectKeys => (false, false),
+                types::queries::ActiveConsensusState::NotYetActive => (false, false),
+                types::queries::ActiveConsensusState::Active { finalizer, .. } => (true, finalizer),
+            },
+        },
+    }
+}
+
+/// The timings of the block `bi` as seen by the node at `node_uri`, which
+/// finalized it at `finalization_time` if known.
+fn block_row(
+    node_uri: &str,
+    bi: &types::queries::BlockInfo,
+    is_baker: bool,
+    is_finalizer: bool,
+    finalization_time: Option<DateTime<Utc>>,
+) -> Row {
+    Row {
+        node: node_uri.to_string(),
+        block_hash: bi.block_hash,
+        block_height: bi.block_height,
+        receive_time: bi.block_receive_time,
+        tx_count: bi.transaction_count,
+        arrive_time: bi.block_arrive_time,
+        execution_time: (bi.block_arrive_time - bi.block_receive_time).num_milliseconds(),
+        block_slot: bi.block_slot,
+        block_slot_time: bi.block_slot_time,
+        block_propagation_time: (bi.block_receive_time - bi.block_slot_time).num_milliseconds(),
+        block_baker_id: bi.block_baker,
+        slot_wall_drift: None,
+        anomaly: None,
+        is_baker,
+        is_finalizer,
+        finalized: bi.finalized,
+        finalization_delay: finalization_time
+            .filter(|_| bi.finalized)
+            .map(|time| (time - bi.block_arrive_time).num_milliseconds()),
+        summary_size_bytes: None,
+        transfers: None,
+        contract_transactions: None,
+        staking_transactions: None,
+        other_transactions: None,
+        rejected_transactions: None,
+        account_creations: None,
+        chain_updates: None,
+        averaged_nodes: None,
+    }
+}
+
+/// The summary of a block and the size of the JSON the node sent it as.
+struct Summary {
+    summary: types::BlockSummary,
+    size: u64,
+}
+
+/// Query the summary of `block` as JSON, since the client of the SDK only
+/// returns the parsed summary. `None` if the node does not serve block
+/// summaries.
+async fn block_summary(
+    client: &mut node_client::Client,
+    endpoint: &NodeEndpoint,
+    max_retries: u32,
+    block: BlockHash,
+) -> anyhow::Result<Option<Summary>> {
+    let json = retry(client, endpoint, max_retries, |mut client| async move {
+        client.get_block_summary_json(&block).await
+    })
+    .await?;
+    json.map(|json| {
+        let summary = serde_json::from_str(&json)
+            .context(format!("cannot parse the summary of block {}", block))?;
+        Ok(Summary {
+            summary,
+            size: json.len() as u64,
+        })
+    })
+    .transpose()
+}
+
+/// Count the transactions in the `summary` of the block of `row` by kind.
+fn count_transactions(row: &mut Row, summary: &types::BlockSummary) {
+    use types::{AccountTransactionEffects as Effects, BlockItemSummaryDetails as Details};
+    let mut transfers = 0;
+    let mut contract_transactions = 0;
+    let mut staking_transactions = 0;
+    let mut other_transactions = 0;
+    let mut rejected_transactions = 0;
+    let mut account_creations = 0;
+    let mut chain_updates = 0;
+    for item in &summary.transaction_summaries {
+        match &item.details {
+            Details::AccountTransaction(details) => match &details.effects {
+                Effects::None { .. } => rejected_transactions += 1,
+                Effects::AccountTransfer { .. }
+                | Effects::AccountTransferWithMemo { .. }
+                | Effects::TransferredWithSchedule { .. }
+                | Effects::TransferredWithScheduleAndMemo { .. }
+                | Effects::TransferredToEncrypted { .. }
+                | Effects::TransferredToPublic { .. }
+                | Effects::EncryptedAmountTransferred { .. }
+                | Effects::EncryptedAmountTransferredWithMemo { .. } => transfers += 1,
+                Effects::ModuleDeployed { .. }
+                | Effects::ContractInitialized { .. }
+                | Effects::ContractUpdateIssued { .. } => contract_transactions += 1,
+                Effects::BakerAdded { .. }
+                | Effects::BakerRemoved { .. }
+                | Effects::BakerStakeUpdated { .. }
+                | Effects::BakerRestakeEarningsUpdated { .. }
+                | Effects::BakerKeysUpdated { .. }
+                // the configure transactions that replace the above since
+                // protocol version 4.
+                | Effects::BakerConfigured { .. }
+                | Effects::DelegationConfigured { .. } => staking_transactions += 1,
+                _ => other_transactions += 1,
+            },
+            Details::AccountCreation(_) => account_creations += 1,
+            Details::Update(_) => chain_updates += 1,
+        }
+    }
+    row.transfers = Some(transfers);
+    row.contract_transactions = Some(contract_transactions);
+    row.staking_transactions = Some(staking_transactions);
+    row.other_transactions = Some(other_transactions);
+    row.rejected_transactions = Some(rejected_transactions);
+    row.account_creations = Some(account_creations);
+    row.chain_updates = Some(chain_updates);
+}
+
+/// Run `query` with a client of the node at `endpoint`, retrying it up to
+/// `max_retries` times.
+async fn retry<T, E, F, Fut>(
+    client: &mut node_client::Client,
+    endpoint: &NodeEndpoint,
+    max_retries: u32,
+    query: F,
+) -> anyhow::Result<T>
+where
+    F: FnMut(node_client::Client) -> Fut,
+    Fut: std::future::Future<Output = Result<T, E>>,
+    anyhow::Error: From<E>,
+{
+    node_client::retry(
+        client,
+        endpoint,
+        max_retries,
+        node_client::Client::connect,
+        &warn,
+        query,
+    )
+    .await
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// A row of `node` for the block at `height` with one transaction. Its
+    /// slot time is `height` seconds after the epoch, it was received 100 ms
+    /// later and executed in 50 ms.
+    pub(crate) fn row(node: &str, height: u64) -> Row {
+        let block_slot_time = Utc.timestamp_opt(height as i64, 0).unwrap();
+        let receive_time = block_slot_time + chrono::Duration::milliseconds(100);
+        Row {
+            node: node.to_string(),
+            block_height: AbsoluteBlockHeight { height },
+            block_hash: BlockHash::new([height as u8; 32]),
+            receive_time,
+            arrive_time: receive_time + chrono::Duration::milliseconds(50),
+            tx_count: 1,
+            execution_time: 50,
+            block_slot: Slot { slot: height },
+            block_slot_time,
+            block_propagation_time: 100,
+            block_baker_id: None,
+            slot_wall_drift: None,
+            anomaly: None,
+            is_baker: false,
+            is_finalizer: false,
+            finalized: false,
+            finalization_delay: None,
+            summary_size_bytes: None,
+            transfers: None,
+            contract_transactions: None,
+            staking_transactions: None,
+            other_transactions: None,
+            rejected_transactions: None,
+            account_creations: None,
+            chain_updates: None,
+            averaged_nodes: None,
+        }
+    }
+
+    #[test]
+    fn averaged_rows_keep_the_first_node() {
+        let mut slow = row("node-1", 1);
+        slow.execution_time = 150;
+        slow.block_propagation_time = 300;
+        let rows = dedup_blocks(vec![row("node-0", 1), slow, row("node-1", 2)], true);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].node, "node-0");
+        assert_eq!(rows[0].execution_time, 100);
+        assert_eq!(rows[0].block_propagation_time, 200);
+        assert_eq!(rows[0].averaged_nodes, Some(2));
+        // a block of one node is not averaged.
+        assert_eq!(rows[1].node, "node-1");
+        assert_eq!(rows[1].averaged_nodes, None);
+    }
+}
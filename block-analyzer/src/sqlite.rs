@@ -0,0 +1,292 @@
+use crate::Row;
+use anyhow::Context;
+use rusqlite::types::Value;
+use std::path::Path;
+
+/// A column of the `blocks` table with the field of the row it is filled from,
+/// by its name in the serialized row.
+struct Column {
+    name: &'static str,
+    field: &'static str,
+    sql_type: &'static str,
+}
+
+const COLUMNS: &[Column] = &[
+    Column {
+        name: "node",
+        field: "Node id",
+        sql_type: "TEXT NOT NULL",
+    },
+    Column {
+        name: "block_height",
+        field: "Block height",
+        sql_type: "INTEGER NOT NULL",
+    },
+    Column {
+        name: "block_hash",
+        field: "Block hash",
+        sql_type: "TEXT NOT NULL",
+    },
+    Column {
+        name: "receive_time",
+        field: "Receive time",
+        sql_type: "TEXT NOT NULL",
+    },
+    Column {
+        name: "arrive_time",
+        field: "Arrive time",
+        sql_type: "TEXT NOT NULL",
+    },
+    Column {
+        name: "tx_count",
+        field: "Transaction count",
+        sql_type: "INTEGER NOT NULL",
+    },
+    Column {
+        name: "execution_time_ms",
+        field: "Block execution time (millis)",
+        sql_type: "INTEGER NOT NULL",
+    },
+    Column {
+        name: "block_slot",
+        field: "Block slot",
+        sql_type: "INTEGER NOT NULL",
+    },
+    Column {
+        name: "block_slot_time",
+        field: "Block slot time",
+        sql_type: "TEXT NOT NULL",
+    },
+    Column {
+        name: "block_propagation_time_ms",
+        field: "Block propagation time (millis)",
+        sql_type: "INTEGER NOT NULL",
+    },
+    Column {
+        name: "block_baker_id",
+        field: "Block baker id",
+        sql_type: "INTEGER",
+    },
+    Column {
+        name: "slot_wall_drift_ms",
+        field: "Slot wall drift (millis)",
+        sql_type: "INTEGER",
+    },
+    Column {
+        name: "anomaly",
+        field: "Anomaly",
+        sql_type: "INTEGER",
+    },
+    Column {
+        name: "is_baker",
+        field: "Baker",
+        sql_type: "INTEGER NOT NULL",
+    },
+    Column {
+        name: "is_finalizer",
+        field: "Finalizer",
+        sql_type: "INTEGER NOT NULL",
+    },
+    Column {
+        name: "finalized",
+        field: "Finalized",
+        sql_type: "INTEGER NOT NULL",
+    },
+    Column {
+        name: "estimated_finalization_delay_ms",
+        field: "Estimated finalization delay (millis)",
+        sql_type: "INTEGER",
+    },
+    Column {
+        name: "summary_size_bytes",
+        field: "Block summary size (bytes)",
+        sql_type: "INTEGER",
+    },
+    Column {
+        name: "transfers",
+        field: "Transfers",
+        sql_type: "INTEGER",
+    },
+    Column {
+        name: "contract_transactions",
+        field: "Contract transactions",
+        sql_type: "INTEGER",
+    },
+    Column {
+        name: "staking_transactions",
+        field: "Baker and delegation transactions",
+        sql_type: "INTEGER",
+    },
+    Column {
+        name: "other_transactions",
+        field: "Other account transactions",
+        sql_type: "INTEGER",
+    },
+    Column {
+        name: "rejected_transactions",
+        field: "Rejected transactions",
+        sql_type: "INTEGER",
+    },
+    Column {
+        name: "account_creations",
+        field: "Account creations",
+        sql_type: "INTEGER",
+    },
+    Column {
+        name: "chain_updates",
+        field: "Chain updates",
+        sql_type: "INTEGER",
+    },
+    Column {
+        name: "averaged_nodes",
+        field: "Averaged nodes",
+        sql_type: "INTEGER",
+    },
+];
+
+/// A SQLite database the rows of every run are appended to, in the `blocks`
+/// table with a column per field of the rows and the `run_id` of the run.
+pub(crate) struct Database {
+    connection: rusqlite::Connection,
+    run_id: String,
+}
+
+impl Database {
+    /// Open the database at `path`, creating it and the table if needed.
+    pub(crate) fn open(path: &Path, run_id: String) -> anyhow::Result<Database> {
+        let connection = rusqlite::Connection::open(path)
+            .context(format!("cannot open the database {}", path.display()))?;
+        let columns: Vec<String> = COLUMNS
+            .iter()
+            .map(|column| format!("{} {}", column.name, column.sql_type))
+            .collect();
+        connection
+            .execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS blocks (run_id TEXT NOT NULL, {});
+                 CREATE INDEX IF NOT EXISTS blocks_block_height ON blocks (block_height);
+                 CREATE INDEX IF NOT EXISTS blocks_node ON blocks (node);",
+                columns.join(", ")
+            ))
+            .context(format!(
+                "cannot create the blocks table in {}",
+                path.display()
+            ))?;
+        Ok(Database { connection, run_id })
+    }
+
+    pub(crate) fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// Insert the `rows` in one transaction.
+    pub(crate) fn insert(&mut self, rows: &[Row]) -> anyhow::Result<()> {
+        let names: Vec<&str> = COLUMNS.iter().map(|column| column.name).collect();
+        let placeholders: Vec<String> =
+            (1..=COLUMNS.len() + 1).map(|i| format!("?{}", i)).collect();
+        let sql = format!(
+            "INSERT INTO blocks (run_id, {}) VALUES ({})",
+            names.join(", "),
+            placeholders.join(", ")
+        );
+        let transaction = self.connection.transaction()?;
+        {
+            let mut statement = transaction
+                .prepare_cached(&sql)
+                .context("the blocks table does not match the rows")?;
+            for row in rows {
+                let mut values = vec![Value::Text(self.run_id.clone())];
+                values.extend(row_values(row)?);
+                statement
+                    .execute(rusqlite::params_from_iter(values))
+                    .context("cannot insert a row into the database")?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+/// The values of the columns for `row`. The row is serialized like it is for
+/// the other formats, so the hashes and times are the same text as in those.
+fn row_values(row: &Row) -> anyhow::Result<Vec<Value>> {
+    let serde_json::Value::Object(fields) = serde_json::to_value(row)? else {
+        anyhow::bail!("a row is not serialized as an object");
+    };
+    Ok(COLUMNS
+        .iter()
+        .map(|column| match fields.get(column.field) {
+            None | Some(serde_json::Value::Null) => Value::Null,
+            Some(serde_json::Value::Bool(b)) => Value::Integer(i64::from(*b)),
+            Some(serde_json::Value::Number(n)) => match n.as_i64() {
+                Some(n) => Value::Integer(n),
+                None => Value::Real(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Some(serde_json::Value::String(s)) => Value::Text(s.clone()),
+            Some(other) => Value::Text(other.to_string()),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::row;
+
+    #[test]
+    fn every_field_has_a_column() {
+        let serde_json::Value::Object(fields) = serde_json::to_value(row("node-0", 1)).unwrap()
+        else {
+            panic!("a row is not serialized as an object");
+        };
+        for column in COLUMNS {
+            assert!(
+                fields.contains_key(column.field),
+                "the column {} is filled from the missing field {:?}",
+                column.name,
+                column.field
+            );
+        }
+        assert_eq!(
+            fields.len(),
+            COLUMNS.len(),
+            "a field of the rows has no column"
+        );
+    }
+
+    #[test]
+    fn rows_are_inserted_with_their_run() {
+        let mut database = Database::open(Path::new(":memory:"), "run-1".to_string()).unwrap();
+        let mut slow = row("node-1", 2);
+        slow.execution_time = 700;
+        slow.anomaly = Some(true);
+        database.insert(&[row("node-0", 1), slow]).unwrap();
+        let (run_id, node, height, execution_time, anomaly): (String, String, i64, i64, Option<i64>) =
+            database
+                .connection
+                .query_row(
+                    "SELECT run_id, node, block_height, execution_time_ms, anomaly FROM blocks ORDER BY block_height DESC",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+                )
+                .unwrap();
+        assert_eq!(
+            (
+                run_id.as_str(),
+                node.as_str(),
+                height,
+                execution_time,
+                anomaly
+            ),
+            ("run-1", "node-1", 2, 700, Some(1))
+        );
+        let empty: Option<i64> = database
+            .connection
+            .query_row(
+                "SELECT transfers FROM blocks WHERE block_height = 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(empty, None);
+    }
+}
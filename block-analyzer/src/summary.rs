@@ -0,0 +1,132 @@
+use crate::Row;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Statistics of a timing in milliseconds.
+struct Stats {
+    count: usize,
+    mean: f64,
+    std_dev: f64,
+    median: i64,
+    p95: i64,
+    max: i64,
+}
+
+impl Stats {
+    fn new(mut values: Vec<i64>) -> Option<Stats> {
+        values.sort_unstable();
+        let max = *values.last()?;
+        let count = values.len();
+        let mean = values.iter().sum::<i64>() as f64 / count as f64;
+        let variance = values
+            .iter()
+            .map(|&value| (value as f64 - mean).powi(2))
+            .sum::<f64>()
+            / count as f64;
+        Some(Stats {
+            count,
+            mean,
+            std_dev: variance.sqrt(),
+            median: percentile(&values, 0.5),
+            p95: percentile(&values, 0.95),
+            max,
+        })
+    }
+}
+
+/// The nearest-rank percentile `p` of the non-empty `sorted` values.
+pub(crate) fn percentile(sorted: &[i64], p: f64) -> i64 {
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.saturating_sub(1)]
+}
+
+/// The time in milliseconds between every two `rows` of consecutive heights,
+/// which are sorted by height, taken from each with `time`. The rows around a
+/// gap, e.g. of skipped or filtered blocks, are not compared.
+fn intervals(rows: &[&Row], time: fn(&Row) -> DateTime<Utc>) -> Vec<i64> {
+    rows.windows(2)
+        .filter(|pair| pair[1].block_height.height == pair[0].block_height.height + 1)
+        .map(|pair| (time(pair[1]) - time(pair[0])).num_milliseconds())
+        .collect()
+}
+
+/// Write a table with the statistics of the execution and propagation times of
+/// the `rows` of every node, and of the intervals between their blocks by slot
+/// time and by arrive time.
+pub(crate) fn write_summary(rows: &[Row], out: &mut dyn Write) -> anyhow::Result<()> {
+    let mut nodes: BTreeMap<&str, Vec<&Row>> = BTreeMap::new();
+    for row in rows {
+        nodes.entry(&row.node).or_default().push(row);
+    }
+    writeln!(
+        out,
+        "{:<30} {:<18} {:>8} {:>10} {:>10} {:>8} {:>8} {:>8}",
+        "Node", "Time (millis)", "Count", "Mean", "Std dev", "Median", "P95", "Max"
+    )?;
+    for (node, mut rows) in nodes {
+        rows.sort_by_key(|row| row.block_height.height);
+        let times = [
+            (
+                "execution",
+                rows.iter().map(|row| row.execution_time).collect(),
+            ),
+            (
+                "propagation",
+                rows.iter().map(|row| row.block_propagation_time).collect(),
+            ),
+            ("slot interval", intervals(&rows, |row| row.block_slot_time)),
+            ("arrive interval", intervals(&rows, |row| row.arrive_time)),
+        ];
+        for (name, values) in times {
+            if let Some(stats) = Stats::new(values) {
+                writeln!(
+                    out,
+                    "{:<30} {:<18} {:>8} {:>10.1} {:>10.1} {:>8} {:>8} {:>8}",
+                    node,
+                    name,
+                    stats.count,
+                    stats.mean,
+                    stats.std_dev,
+                    stats.median,
+                    stats.p95,
+                    stats.max
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::row;
+
+    #[test]
+    fn intervals_skip_gaps() {
+        let rows = [
+            row("node-0", 1),
+            row("node-0", 2),
+            row("node-0", 5),
+            row("node-0", 6),
+        ];
+        let rows: Vec<_> = rows.iter().collect();
+        assert_eq!(intervals(&rows, |row| row.block_slot_time), [1000, 1000]);
+    }
+
+    #[test]
+    fn summary_has_a_line_per_timing() {
+        let rows = vec![row("node-0", 2), row("node-0", 1), row("node-0", 4)];
+        let mut out = vec![];
+        write_summary(&rows, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let slot = out
+            .lines()
+            .find(|line| line.contains("slot interval"))
+            .unwrap();
+        let columns: Vec<_> = slot.split_whitespace().collect();
+        assert_eq!(columns[3], "1");
+        assert_eq!(columns[7], "1000");
+    }
+}
@@ -0,0 +1,79 @@
+use crate::Row;
+use chrono::{DateTime, TimeZone, Utc};
+use concordium_rust_sdk::common::SerdeSerialize;
+use std::collections::{BTreeMap, HashSet};
+
+/// The transactions of the blocks with a slot time in a window.
+#[derive(SerdeSerialize)]
+pub(crate) struct TpsRow {
+    #[serde(rename = "Window start")]
+    window_start: DateTime<Utc>,
+    #[serde(rename = "Blocks")]
+    blocks: u64,
+    #[serde(rename = "Transactions")]
+    transactions: u64,
+    #[serde(rename = "TPS")]
+    tps: f64,
+}
+
+/// Bucket the blocks of the `rows` by their slot time into windows of
+/// `window_secs` seconds, aligned to the unix epoch. A block that is in the
+/// rows of several nodes is counted once, and the windows without blocks
+/// between the first and the last are included with no transactions.
+pub(crate) fn windows(rows: &[Row], window_secs: i64) -> Vec<TpsRow> {
+    let mut seen = HashSet::new();
+    // the number of blocks and transactions in each window.
+    let mut windows: BTreeMap<i64, (u64, u64)> = BTreeMap::new();
+    for row in rows {
+        if seen.insert(row.block_hash) {
+            let window = row.block_slot_time.timestamp().div_euclid(window_secs);
+            let (blocks, transactions) = windows.entry(window).or_default();
+            *blocks += 1;
+            *transactions += row.tx_count;
+        }
+    }
+    let (Some(&first), Some(&last)) = (windows.keys().next(), windows.keys().next_back()) else {
+        return vec![];
+    };
+    (first..=last)
+        .map(|window| {
+            let (blocks, transactions) = windows.get(&window).copied().unwrap_or_default();
+            TpsRow {
+                window_start: Utc.timestamp_opt(window * window_secs, 0).unwrap(),
+                blocks,
+                transactions,
+                tps: transactions as f64 / window_secs as f64,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::row;
+
+    #[test]
+    fn blocks_are_counted_once_per_window() {
+        // the slot times are 3, 8 and 25 seconds, and block 8 is seen twice.
+        let mut rows = vec![row("node-0", 3), row("node-0", 8), row("node-1", 8)];
+        rows[1].tx_count = 9;
+        rows[2].tx_count = 9;
+        rows.push(row("node-0", 25));
+
+        let windows: Vec<_> = windows(&rows, 10)
+            .iter()
+            .map(|row| {
+                (
+                    row.window_start.timestamp(),
+                    row.blocks,
+                    row.transactions,
+                    row.tps,
+                )
+            })
+            .collect();
+        // the empty window in between is reported as well.
+        assert_eq!(windows, [(0, 2, 10, 1.0), (10, 0, 0, 0.0), (20, 1, 1, 0.1)]);
+        assert!(super::windows(&[], 10).is_empty());
+    }
+}
@@ -0,0 +1,121 @@
+use crate::node::{self, Network};
+use crate::Config;
+use anyhow::Context;
+use std::net::TcpListener;
+use std::process::Command;
+
+/// Check everything the nodes need to be spawned, without spawning them, and
+/// print a line for every check. Fails if any check does.
+pub(crate) fn run_checks(cfg: &Config) -> anyhow::Result<()> {
+    // every check is a name and what was found, or why it failed.
+    let mut checks: Vec<(String, anyhow::Result<String>)> = vec![];
+    match &cfg.node_binary {
+        Some(binary) => checks.push((
+            "node binary".to_string(),
+            binary
+                .canonicalize()
+                .context(format!(
+                    "cannot find the node binary at {}",
+                    binary.display()
+                ))
+                .map(|path| path.display().to_string()),
+        )),
+        None => {
+            checks.push(("cargo".to_string(), cargo_version()));
+            checks.push((
+                "node manifest".to_string(),
+                node::node_manifest(cfg).map(|path| path.display().to_string()),
+            ));
+        }
+    }
+    match node::genesis_root(cfg) {
+        Ok(genesis_root) => {
+            checks.push((
+                "genesis root".to_string(),
+                Ok(genesis_root.display().to_string()),
+            ));
+            // the nodes that continue their state do not need genesis.dat.
+            if !cfg.continue_state {
+                checks.push((
+                    "genesis.dat".to_string(),
+                    node::genesis_dat(&genesis_root).map(|path| path.display().to_string()),
+                ));
+            }
+            for i in 0..cfg.num_nodes {
+                if let Some(baker) = node::baker_id(cfg, i) {
+                    checks.push((
+                        format!("node {} credentials of baker {}", i, baker),
+                        node::baker_credentials(&genesis_root, baker)
+                            .map(|path| path.display().to_string()),
+                    ));
+                }
+            }
+        }
+        Err(e) => checks.push(("genesis root".to_string(), Err(e))),
+    }
+    if cfg.auto_ports {
+        checks.push((
+            "ports".to_string(),
+            Ok("free ports are picked when the nodes are spawned".to_string()),
+        ));
+    } else {
+        match node::configured_ports(cfg) {
+            Ok(ports) => {
+                for (i, ports) in ports.iter().enumerate() {
+                    checks.push((
+                        format!("node {} ports", i),
+                        port_free("127.0.0.1", ports.rpc)
+                            .and_then(|()| port_free("0.0.0.0", ports.peer))
+                            .map(|()| {
+                                format!("gRPC {} and p2p {} are free", ports.rpc, ports.peer)
+                            }),
+                    ));
+                }
+            }
+            Err(e) => checks.push(("ports".to_string(), Err(e))),
+        }
+    }
+    // the options are only checked together once the files are there, since
+    // the network checks the files as well.
+    if checks.iter().all(|(_, result)| result.is_ok()) {
+        checks.push((
+            "options".to_string(),
+            Network::new(cfg).map(|_| "valid".to_string()),
+        ));
+    }
+
+    let mut failed = 0;
+    for (name, result) in &checks {
+        match result {
+            Ok(found) => println!("[ ok ] {}: {}", name, found),
+            Err(e) => {
+                failed += 1;
+                println!("[FAIL] {}: {:#}", name, e);
+            }
+        }
+    }
+    anyhow::ensure!(failed == 0, "{} of {} checks failed", failed, checks.len());
+    println!("All {} checks passed", checks.len());
+    Ok(())
+}
+
+/// The version of the cargo on the PATH, which builds and runs the node.
+fn cargo_version() -> anyhow::Result<String> {
+    let output = Command::new("cargo")
+        .arg("--version")
+        .output()
+        .context("cannot run cargo. Install Rust or pass --use-prebuilt-binary")?;
+    anyhow::ensure!(
+        output.status.success(),
+        "cargo --version failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Whether `port` can be listened on at `address`, like the node does.
+fn port_free(address: &str, port: u16) -> anyhow::Result<()> {
+    TcpListener::bind((address, port))
+        .map(drop)
+        .context(format!("port {} is in use", port))
+}
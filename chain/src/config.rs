@@ -0,0 +1,470 @@
+use anyhow::Context;
+use clap::{arg_enum, ArgMatches};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+arg_enum! {
+    /// The level the nodes log at.
+    #[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum NodeLogLevel {
+        Info,
+        Debug,
+        Trace,
+    }
+}
+
+arg_enum! {
+    /// Where the output of the nodes is written besides the ui.
+    #[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum LogSink {
+        File,
+        Syslog,
+        Journald,
+    }
+}
+
+impl NodeLogLevel {
+    /// The option of the node for the level.
+    pub(crate) fn flag(self) -> &'static str {
+        match self {
+            NodeLogLevel::Info => "--info",
+            NodeLogLevel::Debug => "--debug",
+            NodeLogLevel::Trace => "--trace",
+        }
+    }
+}
+
+#[derive(StructOpt, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    #[structopt(
+        long = "num-nodes",
+        help = "The number of nodes to spawn",
+        default_value = "5"
+    )]
+    pub(crate) num_nodes: usize,
+    #[structopt(
+        long = "optimal-connected",
+        help = "default the peers in the network will be connected in line. If optimal-connected is chosen then every peer will have connection to all other peers."
+    )]
+    pub(crate) optimal_connected: bool,
+    #[structopt(
+        long = "ring",
+        help = "Connect the peers in a ring where every peer connects to the next one and the last peer connects to the first.",
+        conflicts_with_all = &["optimal-connected", "topology"]
+    )]
+    pub(crate) ring: bool,
+    #[structopt(
+        long = "genesis-root",
+        help = "Path to genesis_data",
+        default_value = "../deps/concordium-node/scripts/genesis/genesis_data/"
+    )]
+    pub(crate) genesis_root: String,
+    #[structopt(
+        long = "rpc-port-offset",
+        help = "gRPC port offset. The nodes will be spawned this port and incrementing the port number for each",
+        default_value = "7000"
+    )]
+    pub(crate) rpc_port_offset: usize,
+    #[structopt(
+        long = "p2p-port-offset",
+        help = "P2p port offset. The nodes will be spawned this port and incrementing the port number for each",
+        default_value = "8000"
+    )]
+    #[serde(rename = "p2p-port-offset")]
+    pub(crate) peer_port_offset: usize,
+    #[structopt(
+        long = "auto-ports",
+        help = "Let the OS choose free gRPC and p2p ports for the nodes instead of using the port offsets."
+    )]
+    pub(crate) auto_ports: bool,
+    #[structopt(
+        long = "node-manifest",
+        help = "Path to the Cargo.toml of the node.",
+        default_value = "../deps/concordium-node/concordium-node/Cargo.toml",
+        parse(from_os_str)
+    )]
+    pub(crate) node_manifest: PathBuf,
+    #[structopt(
+        long = "use-prebuilt-binary",
+        help = "Run this concordium-node executable directly instead of building and running the node with cargo.",
+        parse(from_os_str)
+    )]
+    #[serde(rename = "use-prebuilt-binary")]
+    pub(crate) node_binary: Option<PathBuf>,
+    #[structopt(long = "rts-flags", help = "RTS flags", default_value = "-N2")]
+    pub(crate) rts_flags: String,
+    #[structopt(
+        long = "housekeeping-interval",
+        help = "Interval in seconds where the node cleans up its connections etc.",
+        default_value = "300"
+    )]
+    pub(crate) housekeeping_interval: usize,
+    #[structopt(
+        long = "continue-state",
+        help = "If this is set then the nodes will use existing data directories."
+    )]
+    pub(crate) continue_state: bool,
+    #[structopt(
+        long = "strict-clean",
+        help = "Fail to start a node whose old data directory cannot be removed, instead of warning that it does not start from a clean state.",
+        conflicts_with = "continue-state"
+    )]
+    pub(crate) strict_clean: bool,
+    #[structopt(long = "no-emit-logs", help = "If true no log files will be emitted.")]
+    pub(crate) no_emit_logs: bool,
+    #[structopt(
+        long = "log-dir",
+        help = "Directory where the full output of every node is written to rotating log files, regardless of --no-emit-logs. If unset the logs are written to peer-N.log in the current directory.",
+        parse(from_os_str)
+    )]
+    pub(crate) log_dir: Option<PathBuf>,
+    #[structopt(
+        long = "log-max-mb",
+        help = "The size in MB at which the log files in --log-dir are rotated. Two rolled segments are kept per node.",
+        default_value = "100"
+    )]
+    pub(crate) log_max_mb: u64,
+    #[structopt(
+        long = "log-sink",
+        help = "Write the output of the nodes to peer-N.log (file), or instead to syslog or systemd-journald, tagged with the node. The files in --log-dir are written either way.",
+        possible_values = &LogSink::variants(),
+        case_insensitive = true,
+        default_value = "file"
+    )]
+    pub(crate) log_sink: LogSink,
+    #[structopt(
+        long = "tui-buffer-lines",
+        help = "The number of lines of the log of each node kept in the ui. Older lines are dropped from the ui, the log files keep them.",
+        default_value = "5000"
+    )]
+    pub(crate) tui_buffer_lines: usize,
+    #[structopt(
+        long = "log-batch-lines",
+        help = "The most lines of a node that are forwarded to the ui and the log file at once. Fewer lines are forwarded as soon as there are no more.",
+        default_value = "10"
+    )]
+    pub(crate) log_batch_lines: usize,
+    #[structopt(
+        long = "no-color-logs",
+        help = "Show the logs without coloring the severity levels."
+    )]
+    pub(crate) no_color_logs: bool,
+    #[structopt(
+        long = "accounts-cache-size",
+        help = "The size of the accounts cache size",
+        default_value = "40000"
+    )]
+    pub(crate) accounts_cache_size: usize,
+    #[structopt(
+        long = "shutdown-grace-secs",
+        help = "Seconds to wait for the nodes to exit after asking them to terminate before killing them.",
+        default_value = "10"
+    )]
+    pub(crate) shutdown_grace_secs: u64,
+    #[structopt(
+        long = "config",
+        help = "TOML file with the configuration. Options given on the command line take precedence."
+    )]
+    pub(crate) config: Option<PathBuf>,
+    #[structopt(
+        long = "topology",
+        help = "JSON file with the directed connections between the nodes, e.g. [[0,1],[1,2]]. Replaces the line or fully connected layout. A third element sets a latency in milliseconds, e.g. [1,2,250]. The latency applies to all traffic of the node the edge points to, the largest of its incoming edges.",
+        conflicts_with = "optimal-connected"
+    )]
+    pub(crate) topology: Option<PathBuf>,
+    #[structopt(
+        long = "num-bakers",
+        help = "The number of nodes that bake. In a line the bakers are the last nodes, otherwise the first. Defaults to 1 in a line and to 5 otherwise, limited by the number of nodes."
+    )]
+    pub(crate) num_bakers: Option<usize>,
+    #[structopt(
+        long = "height-divergence",
+        help = "Flag nodes whose best block height is more than this many blocks behind the highest node.",
+        default_value = "5"
+    )]
+    pub(crate) height_divergence: u64,
+    #[structopt(
+        long = "headless",
+        help = "Print the logs of all nodes to stdout instead of showing the terminal ui. The nodes are stopped with Ctrl-C."
+    )]
+    pub(crate) headless: bool,
+    #[structopt(
+        long = "emit-topology",
+        help = "Write the connections between the nodes to this file as a Graphviz DOT graph before spawning them. Bakers are drawn as boxes.",
+        parse(from_os_str)
+    )]
+    pub(crate) emit_topology: Option<PathBuf>,
+    #[structopt(
+        long = "check",
+        help = "Check that cargo or the node binary, the node manifest, the genesis data and the credentials of the bakers are there and that the ports are free, print the result of every check and exit without spawning the nodes. Exits with an error if any check fails."
+    )]
+    pub(crate) check: bool,
+    #[structopt(
+        long = "summary",
+        help = "File the summary of the session is written to as JSON when the nodes are shut down, with the uptime, restarts and exit status of every node and this configuration.",
+        default_value = "summary.json",
+        parse(from_os_str)
+    )]
+    pub(crate) summary: PathBuf,
+    #[structopt(
+        long = "latency-ms",
+        help = "Delay the p2p traffic of every node by this many milliseconds with tc netem on the loopback interface. Edges in the topology file can set their own latency. Linux only, needs root or CAP_NET_ADMIN. At most 13 different latencies are supported."
+    )]
+    pub(crate) latency_ms: Option<u64>,
+    #[structopt(
+        long = "ready-timeout-secs",
+        help = "Seconds to wait after spawning for all nodes to answer gRPC queries before the network is reported as up with the nodes that are ready.",
+        default_value = "60"
+    )]
+    pub(crate) ready_timeout_secs: u64,
+    #[structopt(
+        long = "node-log-level",
+        help = "The level the nodes log at. The BlockExecution metric of log-analyzer needs debug. Without it the nodes log warnings and errors only.",
+        possible_values = &NodeLogLevel::variants(),
+        case_insensitive = true
+    )]
+    pub(crate) node_log_level: Option<NodeLogLevel>,
+    /// Per node overrides, only available via the config file.
+    #[structopt(skip)]
+    #[serde(rename = "node")]
+    pub(crate) nodes: BTreeMap<usize, NodeConfig>,
+}
+
+/// Settings for a single node that override the ones of the network.
+#[derive(Deserialize, Serialize, Default, Clone)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct NodeConfig {
+    /// RTS flags of the node.
+    pub(crate) rts_flags: Option<String>,
+    /// Index of the baker credentials the node should use.
+    pub(crate) baker: Option<usize>,
+    /// Environment variables of the node, set after the common ones so they
+    /// take precedence.
+    #[serde(default)]
+    pub(crate) env: BTreeMap<String, String>,
+}
+
+/// The contents of a config file. The keys are the same as the long names of
+/// the command line options, and nodes are configured in `[node.N]` tables.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct FileConfig {
+    num_nodes: Option<usize>,
+    optimal_connected: Option<bool>,
+    ring: Option<bool>,
+    genesis_root: Option<String>,
+    rpc_port_offset: Option<usize>,
+    #[serde(rename = "p2p-port-offset")]
+    peer_port_offset: Option<usize>,
+    auto_ports: Option<bool>,
+    node_manifest: Option<PathBuf>,
+    #[serde(rename = "use-prebuilt-binary")]
+    node_binary: Option<PathBuf>,
+    rts_flags: Option<String>,
+    housekeeping_interval: Option<usize>,
+    continue_state: Option<bool>,
+    strict_clean: Option<bool>,
+    no_emit_logs: Option<bool>,
+    log_dir: Option<PathBuf>,
+    log_max_mb: Option<u64>,
+    log_sink: Option<LogSink>,
+    tui_buffer_lines: Option<usize>,
+    log_batch_lines: Option<usize>,
+    no_color_logs: Option<bool>,
+    accounts_cache_size: Option<usize>,
+    shutdown_grace_secs: Option<u64>,
+    topology: Option<PathBuf>,
+    num_bakers: Option<usize>,
+    height_divergence: Option<u64>,
+    headless: Option<bool>,
+    emit_topology: Option<PathBuf>,
+    summary: Option<PathBuf>,
+    latency_ms: Option<u64>,
+    ready_timeout_secs: Option<u64>,
+    node_log_level: Option<NodeLogLevel>,
+    #[serde(default)]
+    node: BTreeMap<String, NodeConfig>,
+}
+
+/// Take the value of each field from the file unless the option was given on
+/// the command line. Optional fields of the config are set to `Some` value.
+/// structopt names every argument after its field in kebab case, not after its
+/// `long` name, e.g. `peer-port-offset` for `--p2p-port-offset`.
+macro_rules! merge {
+    ($cfg:ident, $file:ident, $matches:ident, $($field:ident),*) => {
+        $(
+            if let Some(value) = $file.$field {
+                if $matches.occurrences_of(&stringify!($field).replace('_', "-")) == 0 {
+                    $cfg.$field = value.into();
+                }
+            }
+        )*
+    };
+}
+
+impl Config {
+    /// Fill in the options that were not given on the command line from the
+    /// `--config` file if one is set. `matches` are the matches the
+    /// configuration was parsed from.
+    pub fn with_config_file(mut self, matches: &ArgMatches) -> anyhow::Result<Config> {
+        if let Some(path) = self.config.clone() {
+            let contents = std::fs::read_to_string(&path)
+                .context(format!("cannot read config file {}", path.display()))?;
+            let file: FileConfig = toml::from_str(&contents)
+                .context(format!("invalid config file {}", path.display()))?;
+            self.merge(file, matches)?;
+        }
+        Ok(self)
+    }
+
+    fn merge(&mut self, file: FileConfig, matches: &ArgMatches) -> anyhow::Result<()> {
+        merge!(
+            self,
+            file,
+            matches,
+            num_nodes,
+            optimal_connected,
+            ring,
+            genesis_root,
+            rpc_port_offset,
+            peer_port_offset,
+            auto_ports,
+            node_manifest,
+            node_binary,
+            rts_flags,
+            housekeeping_interval,
+            continue_state,
+            strict_clean,
+            no_emit_logs,
+            log_dir,
+            log_max_mb,
+            log_sink,
+            tui_buffer_lines,
+            log_batch_lines,
+            no_color_logs,
+            accounts_cache_size,
+            shutdown_grace_secs,
+            topology,
+            num_bakers,
+            height_divergence,
+            headless,
+            emit_topology,
+            summary,
+            latency_ms,
+            ready_timeout_secs,
+            node_log_level
+        );
+        for (key, node) in file.node {
+            let i: usize = key
+                .parse()
+                .context(format!("invalid node index {:?} in config file", key))?;
+            anyhow::ensure!(
+                i < self.num_nodes,
+                "config for node {} but only {} nodes are spawned",
+                i,
+                self.num_nodes
+            );
+            self.nodes.insert(i, node);
+        }
+        Ok(())
+    }
+
+    /// Whether the nodes are connected in a line, which is the default layout.
+    pub(crate) fn is_line(&self) -> bool {
+        !(self.optimal_connected || self.ring || self.topology.is_some())
+    }
+
+    /// The number of nodes that bake.
+    pub(crate) fn num_bakers(&self) -> usize {
+        self.num_bakers.unwrap_or_else(|| {
+            let default = if self.is_line() { 1 } else { 5 };
+            self.num_nodes.min(default)
+        })
+    }
+
+    /// The overrides for node `i`.
+    pub(crate) fn node(&self, i: usize) -> NodeConfig {
+        self.nodes.get(&i).cloned().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse the command line `args` with the config file `toml`, as `main`
+    /// does.
+    fn parse(name: &str, toml: &str, args: &[&str]) -> anyhow::Result<Config> {
+        let path =
+            std::env::temp_dir().join(format!("chain-config-{}-{}.toml", std::process::id(), name));
+        std::fs::write(&path, toml)?;
+        let path_arg = path.to_string_lossy().into_owned();
+        let matches = Config::clap()
+            .get_matches_from_safe(["chain", "--config", &path_arg].iter().chain(args.iter()))?;
+        let cfg = Config::from_clap(&matches).with_config_file(&matches);
+        std::fs::remove_file(&path)?;
+        cfg
+    }
+
+    const FILE: &str = r#"
+p2p-port-offset = 9000
+use-prebuilt-binary = "/opt/concordium-node"
+rts-flags = "-N4"
+
+[node.1]
+rts-flags = "-N1"
+baker = 3
+"#;
+
+    #[test]
+    fn options_are_read_from_the_file() {
+        let cfg = parse("file", FILE, &[]).unwrap();
+        assert_eq!(cfg.peer_port_offset, 9000);
+        assert_eq!(cfg.node_binary, Some(PathBuf::from("/opt/concordium-node")));
+        assert_eq!(cfg.rts_flags, "-N4");
+        assert_eq!(cfg.node(1).rts_flags.as_deref(), Some("-N1"));
+        assert_eq!(cfg.node(1).baker, Some(3));
+        assert!(cfg.node(0).rts_flags.is_none());
+    }
+
+    #[test]
+    fn command_line_takes_precedence_over_the_file() {
+        let cfg = parse(
+            "cli",
+            FILE,
+            &[
+                "--p2p-port-offset",
+                "8500",
+                "--use-prebuilt-binary",
+                "/usr/bin/concordium-node",
+            ],
+        )
+        .unwrap();
+        assert_eq!(cfg.peer_port_offset, 8500);
+        assert_eq!(
+            cfg.node_binary,
+            Some(PathBuf::from("/usr/bin/concordium-node"))
+        );
+        // the options not given on the command line still come from the file.
+        assert_eq!(cfg.rts_flags, "-N4");
+    }
+
+    #[test]
+    fn nodes_beyond_the_spawned_ones_are_errors() {
+        let toml = "[node.7]\nbaker = 1\n";
+        let error = parse("range", toml, &[]).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "config for node 7 but only 5 nodes are spawned"
+        );
+        // the number of nodes of the file counts.
+        let toml = "num-nodes = 8\n[node.7]\nbaker = 1\n";
+        assert_eq!(parse("in-range", toml, &[]).unwrap().node(7).baker, Some(1));
+        assert!(parse("index", "[node.x]\nbaker = 1\n", &[]).is_err());
+    }
+}
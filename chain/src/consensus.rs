@@ -0,0 +1,59 @@
+use node_client::{Client, NodeEndpoint, DEFAULT_TOKEN};
+use std::time::Duration;
+use tonic::transport::Endpoint;
+
+/// How often the consensus status of the nodes is queried.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The heights of the chain as seen by a node.
+#[derive(Clone, Copy)]
+pub(crate) struct Heights {
+    pub(crate) best: u64,
+    pub(crate) finalized: u64,
+}
+
+/// Query the consensus status of the node behind `client`.
+async fn query_heights(mut client: Client) -> anyhow::Result<Heights> {
+    let status = client.get_consensus_status().await?;
+    Ok(Heights {
+        best: status.best_block_height.height,
+        finalized: status.last_finalized_block_height.height,
+    })
+}
+
+/// Poll the heights of node `i` listening for gRPC on `rpc_port` and send
+/// them to `sender`, or `None` while the node cannot be queried. Polling stops
+/// when the receiver is dropped.
+pub(crate) fn spawn_height_monitor(
+    i: usize,
+    rpc_port: u16,
+    sender: tokio::sync::mpsc::Sender<(usize, Option<Heights>)>,
+) -> anyhow::Result<()> {
+    let endpoint = Endpoint::from_shared(format!("http://127.0.0.1:{}", rpc_port))?
+        .connect_timeout(POLL_INTERVAL)
+        .timeout(POLL_INTERVAL);
+    let endpoint = NodeEndpoint::new(endpoint, DEFAULT_TOKEN, None)?;
+    tokio::spawn(async move {
+        // the connection is kept between polls and reestablished when a query
+        // fails, e.g. because the node is still starting or was restarted.
+        let mut client = None;
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            if client.is_none() {
+                client = Client::connect(endpoint.clone()).await.ok();
+            }
+            let heights = match &client {
+                Some(client) => query_heights(client.clone()).await.ok(),
+                None => None,
+            };
+            if heights.is_none() {
+                client = None;
+            }
+            if sender.send((i, heights)).await.is_err() {
+                break;
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use anyhow::Context;
+
+/// Copies logs to the system clipboard, or to a file where there is none,
+/// e.g. in a remote session.
+#[derive(Default)]
+pub(crate) struct Copier {
+    /// Opened at the first copy. It is kept open, since on some platforms the
+    /// copied text is only available while it is.
+    clipboard: Option<arboard::Clipboard>,
+}
+
+impl Copier {
+    /// Copy `text` and return what happened, for the status bar. Without a
+    /// clipboard the text is written to `<name>-copy.txt`.
+    pub(crate) fn copy(&mut self, text: &str, name: &str) -> String {
+        let lines = text.lines().count();
+        let copied = match &mut self.clipboard {
+            Some(clipboard) => clipboard.set_text(text),
+            None => arboard::Clipboard::new().and_then(|mut clipboard| {
+                clipboard.set_text(text)?;
+                self.clipboard = Some(clipboard);
+                Ok(())
+            }),
+        };
+        let e = match copied {
+            Ok(()) => return format!("copied {} lines to the clipboard", lines),
+            Err(e) => e,
+        };
+        let path = format!("{}-copy.txt", name);
+        match std::fs::write(&path, text).context(format!("cannot write {}", path)) {
+            Ok(()) => format!("no clipboard ({}), wrote {} lines to {}", e, lines, path),
+            Err(file_error) => format!("no clipboard ({}), {:#}", e, file_error),
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! Artificial latency between the nodes with `tc netem`.
+//!
+//! All nodes listen on the loopback interface, so the p2p traffic of a node is
+//! told apart by its p2p port. The packets to and from the port of a delayed
+//! node are sent through a netem qdisc, so every connection to the node gets
+//! the latency in both directions. The latency is per node, not per edge: a
+//! node with several delayed edges to it gets the largest of them.
+
+use crate::node::Network;
+#[cfg(target_os = "linux")]
+use anyhow::Context;
+
+/// The most bands a prio qdisc can have.
+const MAX_BANDS: usize = 16;
+
+/// The bands of the prio qdisc that carry the traffic without latency.
+const PLAIN_BANDS: usize = 3;
+
+/// The delays configured on the loopback interface. They are removed when
+/// this is dropped.
+pub(crate) struct Delays;
+
+#[cfg(target_os = "linux")]
+impl Drop for Delays {
+    fn drop(&mut self) {
+        // the netem qdiscs and filters are removed with the root qdisc.
+        if let Err(e) = tc(&["qdisc", "del", "dev", "lo", "root"]) {
+            eprintln!(
+                "Cannot remove the latency from the loopback interface: {:#}",
+                e
+            );
+        }
+    }
+}
+
+/// The p2p port and latency of every node that has a latency.
+fn delayed(network: &Network) -> Vec<(u16, u64)> {
+    network
+        .ports
+        .iter()
+        .enumerate()
+        .filter_map(|(i, ports)| Some((ports.peer, network.latency_ms(i)?)))
+        .collect()
+}
+
+/// The distinct latencies of the `delayed` nodes, sorted.
+fn latencies(delayed: &[(u16, u64)]) -> Vec<u64> {
+    let mut latencies: Vec<u64> = delayed.iter().map(|&(_, latency)| latency).collect();
+    latencies.sort_unstable();
+    latencies.dedup();
+    latencies
+}
+
+/// Check that every distinct latency of the nodes gets a band of the prio
+/// qdisc, so that tc does not fail once the nodes are spawned.
+pub(crate) fn check_latencies(network: &Network) -> anyhow::Result<()> {
+    let latencies = latencies(&delayed(network));
+    anyhow::ensure!(
+        latencies.len() <= MAX_BANDS - PLAIN_BANDS,
+        "the nodes have {} different latencies, but tc netem can only delay them by {} different latencies",
+        latencies.len(),
+        MAX_BANDS - PLAIN_BANDS
+    );
+    Ok(())
+}
+
+/// Delay the p2p traffic of every node that has a latency. Returns `None` if
+/// no node has one.
+#[cfg(target_os = "linux")]
+pub(crate) fn delay_nodes(network: &Network) -> anyhow::Result<Option<Delays>> {
+    let delayed = delayed(network);
+    let latencies = latencies(&delayed);
+    if latencies.is_empty() {
+        return Ok(None);
+    }
+
+    // the first bands of the prio qdisc carry all other traffic as usual,
+    // every latency gets a band of its own after them.
+    let bands = (PLAIN_BANDS + latencies.len()).to_string();
+    let mut root = vec!["qdisc", "add", "dev", "lo", "root", "handle", "1:", "prio"];
+    root.extend(["bands", &bands, "priomap"]);
+    root.extend(["1", "2", "2", "2", "1", "2", "0", "0"]);
+    root.extend(["1", "1", "1", "1", "1", "1", "1", "1"]);
+    tc(&root).context(
+        "cannot add the latency to the loopback interface, this needs root or CAP_NET_ADMIN",
+    )?;
+    // from here on the qdisc is removed again, also if a later step fails.
+    let delays = Delays;
+    for (band, latency) in latencies.iter().enumerate() {
+        tc(&[
+            "qdisc",
+            "add",
+            "dev",
+            "lo",
+            "parent",
+            &band_class(band),
+            "handle",
+            &format!("{:x}:", band + 0x10),
+            "netem",
+            "delay",
+            &format!("{}ms", latency),
+        ])?;
+    }
+    for (port, latency) in delayed {
+        let band = latencies.binary_search(&latency).unwrap_or_default();
+        let port = port.to_string();
+        for direction in ["sport", "dport"] {
+            tc(&[
+                "filter",
+                "add",
+                "dev",
+                "lo",
+                "protocol",
+                "ip",
+                "parent",
+                "1:0",
+                "prio",
+                "1",
+                "u32",
+                "match",
+                "ip",
+                direction,
+                &port,
+                "0xffff",
+                "flowid",
+                &band_class(band),
+            ])?;
+        }
+    }
+    Ok(Some(delays))
+}
+
+/// netem is only available on Linux, elsewhere the nodes are not delayed.
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn delay_nodes(_network: &Network) -> anyhow::Result<Option<Delays>> {
+    Ok(None)
+}
+
+/// The class of the prio qdisc of the `band`-th latency. Class ids are hex
+/// and start at 1.
+#[cfg(target_os = "linux")]
+fn band_class(band: usize) -> String {
+    format!("1:{:x}", band + PLAIN_BANDS + 1)
+}
+
+#[cfg(target_os = "linux")]
+fn tc(args: &[&str]) -> anyhow::Result<()> {
+    let output = std::process::Command::new("tc")
+        .args(args)
+        .output()
+        .context("cannot run tc, is iproute2 installed?")?;
+    anyhow::ensure!(
+        output.status.success(),
+        "tc {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(())
+}
@@ -0,0 +1,1268 @@
+use anyhow::Context;
+use chrono::DateTime;
+use crossterm::{
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use std::io;
+use std::process::Child;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::error::TryRecvError;
+use tui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Alignment, Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans, Text},
+    widgets::{Block, Borders, Paragraph, Tabs, Wrap},
+    Frame, Terminal,
+};
+
+mod check;
+mod config;
+mod consensus;
+mod copy;
+mod latency;
+mod log_file;
+mod log_sink;
+mod node;
+mod summary;
+mod topology;
+mod transfer;
+
+pub use config::Config;
+use consensus::{spawn_height_monitor, Heights};
+use copy::Copier;
+use latency::delay_nodes;
+use node::{log_file_or_warn, shutdown_nodes, spawn_node, Network};
+use summary::write_summary;
+use transfer::send_test_transfer;
+
+/// The number of log lines shown at once for a node.
+const LOG_VIEW_LINES: usize = 34;
+
+/// The tab showing the interleaved logs of all nodes. The tabs of the nodes
+/// follow it.
+const ALL_TAB: usize = 0;
+
+/// The time to wait for a key press before refreshing the ui.
+const TICK_RATE: Duration = Duration::from_millis(250);
+
+/// Liveness of a spawned node process.
+#[derive(Clone, Default)]
+struct NodeStatus {
+    pid: Option<u32>,
+    alive: bool,
+    /// Why the node could not be spawned, shown instead of its log.
+    error: Option<String>,
+    /// Whether new log lines are held back instead of shown.
+    paused: bool,
+    /// Whether the node has closed its log output.
+    finished: bool,
+    /// The chain heights last reported by the node, if it could be queried.
+    heights: Option<Heights>,
+    /// When the running process of the node was spawned.
+    started: Option<Instant>,
+    /// How long the earlier processes of the node ran.
+    uptime: Duration,
+    restarts: u32,
+    /// How the last process of the node that stopped exited.
+    exit_status: Option<String>,
+    /// The number of lines the node logged.
+    lines: usize,
+}
+
+impl NodeStatus {
+    /// Update the status from the process `fork` of the node, keeping how it
+    /// exited if it has.
+    fn update(&mut self, fork: &mut Option<Child>) {
+        self.pid = fork.as_ref().map(Child::id);
+        let exited = fork.as_mut().map(Child::try_wait);
+        self.alive = matches!(exited, Some(Ok(None)));
+        if let Some(Ok(Some(exit_status))) = exited {
+            self.exit_status = Some(exit_status.to_string());
+        }
+        if !self.alive {
+            self.stopped();
+        } else if self.started.is_none() {
+            self.started = Some(Instant::now());
+        }
+    }
+
+    /// Add the time the process of the node ran to its uptime.
+    fn stopped(&mut self) {
+        if let Some(started) = self.started.take() {
+            self.uptime += started.elapsed();
+        }
+    }
+
+    /// How long the processes of the node ran in total.
+    fn uptime(&self) -> Duration {
+        self.uptime
+            + self
+                .started
+                .map_or(Duration::ZERO, |started| started.elapsed())
+    }
+}
+
+/// Whether the network is up, i.e. all spawned nodes answer gRPC queries.
+#[derive(Clone, Copy)]
+enum Startup {
+    /// Waiting for the nodes to answer until the deadline.
+    Waiting(Instant),
+    /// All spawned nodes answered.
+    Ready,
+    /// Not all nodes answered before the deadline.
+    TimedOut(Duration),
+}
+
+/// A search in the log of a tab.
+struct Search {
+    query: String,
+    /// The index of the selected match among all matches.
+    current: usize,
+}
+
+struct App<'a> {
+    pub titles: Vec<&'a str>,
+    pub index: usize,
+    /// Number of lines the log view of each tab is scrolled up from the bottom.
+    /// 0 means that the view follows the newest lines.
+    pub offsets: Vec<usize>,
+    pub status: Vec<NodeStatus>,
+    /// Whether the severity levels in the logs are colored.
+    pub color_logs: bool,
+    /// Nodes whose best block height is more than this behind the highest
+    /// node are flagged.
+    pub height_divergence: u64,
+    /// The search query being typed, if any.
+    pub input: Option<String>,
+    /// The digits of the node number typed so far, only used when there are
+    /// more than ten nodes.
+    pub node_input: String,
+    /// The active search of each tab.
+    pub searches: Vec<Option<Search>>,
+    /// Whether `q` was pressed once and the next `q` quits.
+    pub confirm_quit: bool,
+    /// The tab shown next to the selected one in a split view. The keys act
+    /// on the selected tab, which is the focused pane.
+    pub split: Option<usize>,
+    /// Whether the selected tab is the right pane of the split view.
+    pub focus_right: bool,
+    pub startup: Startup,
+    /// The outcome of the last test transfer, shown until the next key press.
+    pub message: Option<String>,
+    /// How long to wait for the nodes to be ready after spawning them.
+    pub ready_timeout: Duration,
+}
+
+impl<'a> App<'a> {
+    /// Create the app with a tab for each of the nodes named by `titles`
+    /// after the tab with all logs.
+    fn new(
+        titles: &'a [std::string::String],
+        color_logs: bool,
+        height_divergence: u64,
+        ready_timeout: Duration,
+    ) -> App<'a> {
+        App {
+            titles: std::iter::once("All")
+                .chain(titles.iter().map(AsRef::as_ref))
+                .collect(),
+            index: ALL_TAB,
+            offsets: vec![0; titles.len() + 1],
+            status: vec![NodeStatus::default(); titles.len()],
+            color_logs,
+            height_divergence,
+            input: None,
+            node_input: String::new(),
+            searches: (0..=titles.len()).map(|_| None).collect(),
+            confirm_quit: false,
+            split: None,
+            focus_right: false,
+            startup: Startup::Waiting(Instant::now() + ready_timeout),
+            ready_timeout,
+            message: None,
+        }
+    }
+
+    /// The node of the selected tab, if it is not the tab with all logs.
+    pub fn selected_node(&self) -> Option<usize> {
+        self.index.checked_sub(ALL_TAB + 1)
+    }
+
+    pub fn next(&mut self) {
+        if !self.titles.is_empty() {
+            self.index = (self.index + 1) % self.titles.len();
+        }
+    }
+
+    /// Show the selected tab and the one after it side by side, or go back to
+    /// a single tab.
+    pub fn toggle_split(&mut self) {
+        self.split = match self.split {
+            Some(_) => None,
+            None if self.titles.len() > 1 => Some((self.index + 1) % self.titles.len()),
+            None => None,
+        };
+        self.focus_right = false;
+    }
+
+    /// Move the focus to the other pane of the split view.
+    pub fn switch_pane(&mut self) {
+        if let Some(other) = self.split.replace(self.index) {
+            self.index = other;
+            self.focus_right = !self.focus_right;
+        }
+    }
+
+    /// Whether the tab with all logs is shown, in either pane.
+    pub fn shows_all_tab(&self) -> bool {
+        self.index == ALL_TAB || self.split == Some(ALL_TAB)
+    }
+
+    pub fn previous(&mut self) {
+        if self.index > 0 {
+            self.index -= 1;
+        } else if !self.titles.is_empty() {
+            self.index = self.titles.len() - 1;
+        }
+    }
+
+    /// Scroll the selected log one page up, stopping at the first line.
+    pub fn page_up(&mut self, total_lines: usize) {
+        if let Some(offset) = self.offsets.get_mut(self.index) {
+            *offset = (*offset + LOG_VIEW_LINES).min(total_lines.saturating_sub(LOG_VIEW_LINES));
+        }
+    }
+
+    /// Scroll the selected log one page down, stopping at the newest line.
+    pub fn page_down(&mut self) {
+        if let Some(offset) = self.offsets.get_mut(self.index) {
+            *offset = offset.saturating_sub(LOG_VIEW_LINES);
+        }
+    }
+
+    /// Jump to the first line of the selected log.
+    pub fn top(&mut self, total_lines: usize) {
+        if let Some(offset) = self.offsets.get_mut(self.index) {
+            *offset = total_lines.saturating_sub(LOG_VIEW_LINES);
+        }
+    }
+
+    /// Jump back to following the newest lines of the selected log.
+    pub fn bottom(&mut self) {
+        if let Some(offset) = self.offsets.get_mut(self.index) {
+            *offset = 0;
+        }
+    }
+
+    /// Handle a digit typed to jump to a node. With at most ten nodes the node
+    /// is selected at once, otherwise the digits are collected until Enter is
+    /// pressed.
+    pub fn type_node_digit(&mut self, digit: char) {
+        self.node_input.push(digit);
+        if self.status.len() <= 10 {
+            self.select_typed_node();
+        }
+    }
+
+    /// Select the node whose number was typed, ignoring numbers out of range.
+    pub fn select_typed_node(&mut self) {
+        if let Ok(node) = self.node_input.parse::<usize>() {
+            if node < self.status.len() {
+                self.index = node + ALL_TAB + 1;
+            }
+        }
+        self.node_input.clear();
+    }
+
+    /// Handle a key press while a search query is typed. Enter searches the
+    /// selected `log` and escape cancels.
+    pub fn handle_input(&mut self, key: KeyCode, log: &str) {
+        match key {
+            KeyCode::Char(c) => {
+                if let Some(input) = &mut self.input {
+                    input.push(c);
+                }
+            }
+            KeyCode::Backspace => {
+                if let Some(input) = &mut self.input {
+                    input.pop();
+                }
+            }
+            KeyCode::Enter => {
+                let query = self.input.take().unwrap_or_default();
+                self.search(query, log);
+            }
+            KeyCode::Esc => self.input = None,
+            _ => {}
+        }
+    }
+
+    /// Search the selected `log` for `query` and jump to the newest match.
+    /// An empty query clears the search.
+    pub fn search(&mut self, query: String, log: &str) {
+        self.searches[self.index] = if query.is_empty() {
+            None
+        } else {
+            let current = find_matches(log, &query).len().saturating_sub(1);
+            Some(Search { query, current })
+        };
+        self.show_match(log);
+    }
+
+    /// Move to the next newer or older match, wrapping around at the ends.
+    pub fn cycle_match(&mut self, log: &str, newer: bool) {
+        if let Some(search) = &mut self.searches[self.index] {
+            let matches = find_matches(log, &search.query).len();
+            if matches > 0 {
+                search.current = if newer {
+                    (search.current + 1) % matches
+                } else {
+                    (search.current + matches - 1) % matches
+                };
+            }
+        }
+        self.show_match(log);
+    }
+
+    /// Scroll the selected log so that the current match is in the middle.
+    fn show_match(&mut self, log: &str) {
+        if let Some(search) = &self.searches[self.index] {
+            if let Some(&line) = find_matches(log, &search.query).get(search.current) {
+                let total = log.lines().count();
+                self.offsets[self.index] = (total - line - 1)
+                    .saturating_sub(LOG_VIEW_LINES / 2)
+                    .min(total.saturating_sub(LOG_VIEW_LINES));
+            }
+        }
+    }
+
+    /// Check which of the node processes are still running.
+    pub fn update_status(&mut self, forks: &mut [Option<Child>]) {
+        for (status, fork) in self.status.iter_mut().zip(forks) {
+            status.update(fork);
+        }
+    }
+
+    /// The number of nodes that answered the last gRPC query.
+    pub fn ready_nodes(&self) -> usize {
+        self.status
+            .iter()
+            .filter(|status| status.heights.is_some())
+            .count()
+    }
+
+    /// End the startup phase once every node that was spawned answers gRPC
+    /// queries, or when the deadline has passed.
+    pub fn check_startup(&mut self) {
+        if let Startup::Waiting(deadline) = self.startup {
+            if self
+                .status
+                .iter()
+                .all(|status| status.error.is_some() || status.heights.is_some())
+            {
+                self.startup = Startup::Ready;
+            } else if Instant::now() >= deadline {
+                self.startup = Startup::TimedOut(self.ready_timeout);
+            }
+        }
+    }
+
+    /// Whether the best block height of `node` is too far behind the highest
+    /// node.
+    pub fn is_lagging(&self, node: usize) -> bool {
+        let max_best = self
+            .status
+            .iter()
+            .filter_map(|status| status.heights.map(|heights| heights.best))
+            .max();
+        match (self.status[node].heights, max_best) {
+            (Some(heights), Some(max_best)) => max_best - heights.best > self.height_divergence,
+            _ => false,
+        }
+    }
+
+    /// Keep the viewports of scrolled logs in place when new lines are
+    /// appended to the log of `node`.
+    pub fn lines_appended(&mut self, node: usize, new_lines: usize) {
+        for tab in [ALL_TAB, node + ALL_TAB + 1] {
+            if let Some(offset) = self.offsets.get_mut(tab) {
+                if *offset > 0 {
+                    *offset += new_lines;
+                }
+            }
+        }
+    }
+}
+
+/// Spawn the nodes and show their logs in the terminal until the user quits.
+pub async fn run(cfg: Config) -> anyhow::Result<()> {
+    if cfg.check {
+        return check::run_checks(&cfg);
+    }
+    let network = Network::new(&cfg)?;
+    if cfg.auto_ports {
+        for (i, ports) in network.ports.iter().enumerate() {
+            println!(
+                "Node {}: gRPC port {}, p2p port {}",
+                i, ports.rpc, ports.peer
+            );
+        }
+    }
+    if let Some(path) = &cfg.emit_topology {
+        network.write_dot(path)?;
+    }
+    if !cfg!(target_os = "linux") && (0..cfg.num_nodes).any(|i| network.latency_ms(i).is_some()) {
+        println!("Warning: latency between the nodes needs tc netem and is only supported on Linux, the nodes are not delayed");
+    }
+    if cfg.headless {
+        return run_headless(&network).await;
+    }
+
+    // setup terminal
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
+    let mut titles: Vec<String> = vec![];
+    for i in 0..cfg.num_nodes {
+        titles.push(format!("Node {:?}", i));
+    }
+
+    // create app and run it
+    let mut app = App::new(
+        &titles,
+        !cfg.no_color_logs,
+        cfg.height_divergence,
+        Duration::from_secs(cfg.ready_timeout_secs),
+    );
+    let res = run_app(&mut terminal, &mut app, &network);
+
+    // restore terminal
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
+    terminal.show_cursor()?;
+
+    if let Err(err) = res {
+        println!("{:?}", err)
+    }
+
+    Ok(())
+}
+
+/// The processes of the spawned nodes and the channels of their logs.
+struct Nodes {
+    /// `None` for the nodes that could not be spawned.
+    forks: Vec<Option<Child>>,
+    /// The nodes that could not be spawned and why.
+    failures: Vec<(usize, anyhow::Error)>,
+    receivers: Vec<tokio::sync::mpsc::Receiver<String>>,
+    /// Kept so that restarted nodes log to the same channel. These are weak so
+    /// the channel closes when the reader of a node stops.
+    senders: Vec<tokio::sync::mpsc::WeakSender<String>>,
+}
+
+/// Prepare the data directories and spawn all nodes of the network. A node
+/// that fails to spawn does not stop the others, so the spawned processes are
+/// never lost and can be shut down.
+fn start_nodes(network: &Network) -> Nodes {
+    let mut nodes = Nodes {
+        forks: vec![],
+        failures: vec![],
+        receivers: vec![],
+        senders: vec![],
+    };
+    for i in 0..network.cfg.num_nodes {
+        // create a channel for reading stdout of the forked process.
+        let (sender, receiver) = tokio::sync::mpsc::channel(100);
+        nodes.receivers.push(receiver);
+        nodes.senders.push(sender.downgrade());
+        match start_node(network, i, sender) {
+            Ok(fork) => nodes.forks.push(Some(fork)),
+            Err(e) => {
+                nodes.forks.push(None);
+                nodes.failures.push((i, e));
+            }
+        }
+    }
+    nodes
+}
+
+/// Prepare the data directory of node `i` and spawn it, forwarding its log to
+/// `sender`.
+fn start_node(
+    network: &Network,
+    i: usize,
+    sender: tokio::sync::mpsc::Sender<String>,
+) -> anyhow::Result<Child> {
+    let cfg = network.cfg;
+    if !cfg.continue_state {
+        let data_dir = format!("peer-{}", i);
+        match std::fs::remove_dir_all(&data_dir) {
+            Ok(()) => {}
+            // a directory that does not exist is already clean.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => {
+                let message = format!(
+                    "cannot remove the old data directory {}: {}, the node does not start from a clean state",
+                    data_dir, e
+                );
+                anyhow::ensure!(!cfg.strict_clean, "{}", message);
+                // the warning is shown at the top of the log of the node.
+                let _ = sender.try_send(format!("--- warning: {} ---\n", message));
+            }
+        }
+
+        // create the new peer directory
+        std::fs::create_dir_all(format!("peer-{}", i)).context("Cannot create peer directory")?;
+
+        //copy genesis.dat to peer directory.
+        let genesis_dat = network.genesis_dat()?;
+        std::fs::copy(genesis_dat, format!("peer-{}/genesis.dat", i))
+            .context("Cannot copy genesis dat to peer directory")?;
+    }
+
+    let mut cmd = network.node_command(i)?;
+    spawn_node(
+        i,
+        &mut cmd,
+        log_file_or_warn(i, cfg, false, &sender),
+        cfg.log_sink,
+        cfg.log_batch_lines,
+        sender,
+    )
+}
+
+/// Spawn the nodes and print their logs, tagged with the node, to stdout until
+/// Ctrl-C is pressed or all nodes have stopped. The summary is written once
+/// the nodes are shut down.
+async fn run_headless(network: &Network<'_>) -> anyhow::Result<()> {
+    let started = chrono::Utc::now();
+    let Nodes {
+        mut forks,
+        failures,
+        receivers,
+        ..
+    } = start_nodes(network);
+    let mut status = vec![NodeStatus::default(); forks.len()];
+    for (status, fork) in status.iter_mut().zip(&mut forks) {
+        status.update(fork);
+    }
+    if !failures.is_empty() {
+        for (i, e) in &failures {
+            eprintln!("Node {} failed to start: {:#}", i, e);
+            status[*i].error = Some(format!("{:#}", e));
+        }
+        // without a ui to show the failures the network is not started partially.
+        shutdown_nodes(&mut forks, network.cfg)?;
+        write_headless_summary(network, &mut forks, &mut status, started)?;
+        anyhow::bail!(
+            "{} of {} nodes failed to start",
+            failures.len(),
+            forks.len()
+        );
+    }
+    // the delays are removed when this is dropped, after the nodes are shut down.
+    let _delays = match delay_nodes(network) {
+        Ok(delays) => delays,
+        Err(e) => {
+            shutdown_nodes(&mut forks, network.cfg)?;
+            write_headless_summary(network, &mut forks, &mut status, started)?;
+            return Err(e);
+        }
+    };
+    // forward the logs of all nodes to a single channel in the order they arrive.
+    let (sender, mut merged) = tokio::sync::mpsc::channel(100);
+    for (i, mut receiver) in receivers.into_iter().enumerate() {
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            while let Some(log) = receiver.recv().await {
+                if sender.send((i, log)).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(sender);
+
+    let res = loop {
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => break res.context("cannot listen for Ctrl-C"),
+            log = merged.recv() => match log {
+                Some((i, log)) => {
+                    status[i].lines += log.lines().count();
+                    print_tagged(i, &log);
+                }
+                None => break Ok(()),
+            }
+        }
+    };
+    let shutdown = shutdown_nodes(&mut forks, network.cfg);
+    // print what the nodes logged while stopping.
+    while let Ok(Some((i, log))) = tokio::time::timeout(TICK_RATE, merged.recv()).await {
+        status[i].lines += log.lines().count();
+        print_tagged(i, &log);
+    }
+    let summary = write_headless_summary(network, &mut forks, &mut status, started);
+    res.and(shutdown).and(summary)
+}
+
+/// Record how the nodes of a headless session exited, once they are shut
+/// down, and write the summary of the session.
+fn write_headless_summary(
+    network: &Network,
+    forks: &mut [Option<Child>],
+    status: &mut [NodeStatus],
+    started: chrono::DateTime<chrono::Utc>,
+) -> anyhow::Result<()> {
+    for (status, fork) in status.iter_mut().zip(forks) {
+        status.update(fork);
+    }
+    write_summary(&network.cfg.summary, status, network, started)
+}
+
+/// Print the lines of `log` prefixed with `node`.
+fn print_tagged(node: usize, log: &str) {
+    for line in log.lines() {
+        println!("[node {}] {}", node, line);
+    }
+}
+
+/// Spawn the nodes and show them until the user quits. The nodes are shut
+/// down when the ui stops, also if it fails.
+fn run_app<B: Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    network: &Network,
+) -> anyhow::Result<()> {
+    let started = chrono::Utc::now();
+    let mut nodes = start_nodes(network);
+    let res = show_nodes(terminal, app, network, &mut nodes);
+    for receiver in &mut nodes.receivers {
+        receiver.close();
+    }
+    let shutdown = shutdown_nodes(&mut nodes.forks, network.cfg);
+    app.update_status(&mut nodes.forks);
+    let summary = write_summary(&network.cfg.summary, &app.status, network, started);
+    res.and(shutdown).and(summary)
+}
+
+fn show_nodes<B: Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    network: &Network,
+    nodes: &mut Nodes,
+) -> anyhow::Result<()> {
+    let cfg = network.cfg;
+    // the delays are removed again when the ui stops.
+    let _delays = delay_nodes(network)?;
+    let Nodes {
+        forks,
+        failures,
+        receivers: stdout_receivers,
+        senders,
+    } = nodes;
+    let mut log_buffers = vec![String::new(); cfg.num_nodes];
+    for (i, e) in failures.drain(..) {
+        log_buffers[i].push_str(&format!("--- node failed to start: {:#} ---\n", e));
+        app.status[i].error = Some(format!("{:#}", e));
+        app.status[i].finished = true;
+    }
+    // the lines logged by paused nodes, shown when they are resumed.
+    let mut held_logs = vec![String::new(); cfg.num_nodes];
+    let mut copier = Copier::default();
+    // the logs of all nodes interleaved, only merged when they are shown.
+    let mut merged_log = String::new();
+    let mut merged_outdated = true;
+
+    // poll the chain heights of the nodes.
+    let (heights_sender, mut heights_receiver) = tokio::sync::mpsc::channel(100);
+    for (i, ports) in network.ports.iter().enumerate() {
+        spawn_height_monitor(i, ports.rpc, heights_sender.clone())?;
+    }
+    drop(heights_sender);
+    // the outcomes of the test transfers.
+    let (transfer_sender, mut transfer_receiver) = tokio::sync::mpsc::channel(10);
+    // the nodes are spawned now, so the wait for them starts here.
+    app.startup = Startup::Waiting(Instant::now() + app.ready_timeout);
+
+    // run until someone presses `q` twice.
+    loop {
+        while let Ok((i, heights)) = heights_receiver.try_recv() {
+            app.status[i].heights = heights;
+        }
+        app.check_startup();
+        while let Ok(message) = transfer_receiver.try_recv() {
+            app.message = Some(message);
+        }
+        // append to the logs
+        for (i, receiver) in stdout_receivers.iter_mut().enumerate() {
+            loop {
+                match receiver.try_recv() {
+                    Ok(log) if app.status[i].paused => {
+                        app.status[i].lines += log.lines().count();
+                        held_logs[i].push_str(&log);
+                    }
+                    Ok(log) => {
+                        app.status[i].lines += log.lines().count();
+                        log_buffers[i].push_str(&log);
+                        app.lines_appended(i, log.lines().count());
+                        merged_outdated = true;
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        if !app.status[i].finished {
+                            app.status[i].finished = true;
+                            let ended = "--- node output ended ---\n";
+                            if app.status[i].paused {
+                                held_logs[i].push_str(ended);
+                            } else {
+                                log_buffers[i].push_str(ended);
+                                app.lines_appended(i, 1);
+                                merged_outdated = true;
+                            }
+                        }
+                        break;
+                    }
+                }
+            }
+            // the log files keep all lines, the ui only the newest.
+            trim_log(&mut log_buffers[i], cfg.tui_buffer_lines);
+            trim_log(&mut held_logs[i], cfg.tui_buffer_lines);
+        }
+        app.update_status(forks);
+        if merged_outdated && app.shows_all_tab() {
+            merged_log = merge_logs(&log_buffers);
+            merged_outdated = false;
+        }
+        // draw the ui
+        terminal.draw(|f| ui(f, app, &log_buffers, &merged_log).unwrap())?;
+        if !event::poll(TICK_RATE)? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            let current_log = match app.selected_node() {
+                Some(i) => &log_buffers[i],
+                None => &merged_log,
+            };
+            let total_lines = current_log.lines().count();
+            if app.input.is_some() {
+                app.handle_input(key.code, current_log);
+                continue;
+            }
+            app.message = None;
+            // a stray `q` must not stop the network, so it has to be confirmed
+            // and any other key cancels.
+            if key.code == KeyCode::Char('q') && app.confirm_quit {
+                return Ok(());
+            }
+            if app.confirm_quit {
+                app.confirm_quit = false;
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') => app.confirm_quit = true,
+                KeyCode::Char('r') => {
+                    if let Some((i, fork)) = app
+                        .selected_node()
+                        .and_then(|i| Some((i, forks.get_mut(i)?)))
+                    {
+                        let sender = match senders[i].upgrade() {
+                            Some(sender) => sender,
+                            None => {
+                                // the output of the old process has ended so the channel must be replaced.
+                                let (sender, receiver) = tokio::sync::mpsc::channel(100);
+                                senders[i] = sender.downgrade();
+                                stdout_receivers[i] = receiver;
+                                sender
+                            }
+                        };
+                        app.status[i].finished = false;
+                        // the old process is shut down even if the new one
+                        // cannot be spawned, and how it exited is kept.
+                        let shutdown = shutdown_nodes(std::slice::from_mut(fork), cfg);
+                        app.status[i].update(fork);
+                        // a node that cannot be spawned again shows why in its
+                        // tab and does not count as restarted.
+                        match shutdown.and_then(|()| network.restart_node(i, fork, sender)) {
+                            Ok(()) => {
+                                app.status[i].restarts += 1;
+                                app.status[i].error = None;
+                            }
+                            Err(e) => app.status[i].error = Some(format!("{:#}", e)),
+                        }
+                    }
+                }
+                KeyCode::Char(' ') => {
+                    if let Some(i) = app.selected_node() {
+                        app.status[i].paused = !app.status[i].paused;
+                        if !app.status[i].paused {
+                            let held = std::mem::take(&mut held_logs[i]);
+                            log_buffers[i].push_str(&held);
+                            app.lines_appended(i, held.lines().count());
+                            merged_outdated = true;
+                        }
+                    }
+                }
+                KeyCode::Char('t') => match app.selected_node() {
+                    Some(i) => {
+                        app.message = Some(format!("sending a transfer to node {}…", i));
+                        let sender = transfer_sender.clone();
+                        let genesis_root = network.genesis_root.clone();
+                        let rpc_port = network.ports[i].rpc;
+                        tokio::spawn(async move {
+                            let message = match send_test_transfer(&genesis_root, rpc_port).await {
+                                Ok(hash) => format!("node {} accepted transfer {}", i, hash),
+                                Err(e) => format!("transfer to node {} failed: {:#}", i, e),
+                            };
+                            let _ = sender.send(message).await;
+                        });
+                    }
+                    None => app.message = Some("select a node to send a transfer to".to_string()),
+                },
+                KeyCode::Char('y') | KeyCode::Char('Y') => {
+                    // `Y` copies the whole log instead of the visible lines.
+                    let text = if key.code == KeyCode::Char('Y') {
+                        current_log.clone()
+                    } else {
+                        let lines: Vec<&str> = current_log.lines().collect();
+                        let (start, end) = visible_window(lines.len(), app.offsets[app.index]);
+                        lines[start..end].join("\n")
+                    };
+                    let name = match app.selected_node() {
+                        Some(i) => format!("node-{}", i),
+                        None => "all".to_string(),
+                    };
+                    app.message = Some(copier.copy(&text, &name));
+                }
+                KeyCode::Char('s') => app.toggle_split(),
+                KeyCode::Tab => app.switch_pane(),
+                KeyCode::Right => app.next(),
+                KeyCode::Left => app.previous(),
+                KeyCode::PageUp => app.page_up(total_lines),
+                KeyCode::PageDown => app.page_down(),
+                KeyCode::Home => app.top(total_lines),
+                KeyCode::End => app.bottom(),
+                KeyCode::Char('/') => app.input = Some(String::new()),
+                KeyCode::Char('n') => app.cycle_match(current_log, true),
+                KeyCode::Char('N') => app.cycle_match(current_log, false),
+                KeyCode::Char(c) if c.is_ascii_digit() => app.type_node_digit(c),
+                KeyCode::Enter => app.select_typed_node(),
+                KeyCode::Esc => app.node_input.clear(),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn ui<B: Backend>(
+    f: &mut Frame<B>,
+    app: &App,
+    logs: &[String],
+    merged_log: &str,
+) -> anyhow::Result<()> {
+    let size = f.size();
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(5)
+        .constraints(
+            [
+                Constraint::Length(3),
+                Constraint::Min(0),
+                Constraint::Length(1),
+            ]
+            .as_ref(),
+        )
+        .split(size);
+
+    let block = Block::default().style(Style::default().bg(Color::White).fg(Color::Black));
+    f.render_widget(block, size);
+    let all_title = Spans::from(app.titles[ALL_TAB]);
+    let node_titles = app
+        .titles
+        .iter()
+        .skip(ALL_TAB + 1)
+        .zip(&app.status)
+        .enumerate()
+        .map(|(i, (t, status))| {
+            let color = if !status.alive {
+                Color::Red
+            } else if app.is_lagging(i) {
+                Color::Magenta
+            } else {
+                Color::Green
+            };
+            let mut title = match status.pid {
+                Some(pid) => format!("{} ({})", t, pid),
+                None => t.to_string(),
+            };
+            if let Some(heights) = status.heights {
+                title.push_str(&format!(" {}/{}", heights.best, heights.finalized));
+            }
+            if app.is_lagging(i) {
+                title.push_str(" !");
+            }
+            if status.paused {
+                title.push_str(" paused");
+            }
+            Spans::from(Span::styled(title, Style::default().fg(color)))
+        });
+    let titles = std::iter::once(all_title).chain(node_titles).collect();
+    let tabs = Tabs::new(titles)
+        .block(Block::default().borders(Borders::ALL).title("Tabs"))
+        .select(app.index)
+        .style(Style::default().fg(Color::Cyan))
+        .highlight_style(
+            Style::default()
+                .add_modifier(Modifier::BOLD)
+                .bg(Color::Black),
+        );
+    f.render_widget(tabs, chunks[0]);
+
+    match app.split {
+        Some(other) => {
+            let panes = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+                .split(chunks[1]);
+            let (left, right) = if app.focus_right {
+                (other, app.index)
+            } else {
+                (app.index, other)
+            };
+            f.render_widget(view_tab(app, left, logs, merged_log)?, panes[0]);
+            f.render_widget(view_tab(app, right, logs, merged_log)?, panes[1]);
+        }
+        None => f.render_widget(view_tab(app, app.index, logs, merged_log)?, chunks[1]),
+    }
+
+    let (log, _) = tab_log(app, app.index, logs, merged_log)?;
+    let search = app.searches[app.index].as_ref();
+
+    let status = if app.confirm_quit {
+        "Press q again to quit, any other key to cancel".to_string()
+    } else if let Some(input) = &app.input {
+        format!("/{}", input)
+    } else if !app.node_input.is_empty() {
+        format!("node {} (Enter: jump, Esc: cancel)", app.node_input)
+    } else if let Some(message) = &app.message {
+        message.clone()
+    } else if let Some(search) = search {
+        let matches = find_matches(log, &search.query).len();
+        if matches == 0 {
+            format!("/{} no matches", search.query)
+        } else {
+            format!(
+                "/{} match {}/{} (n: newer, N: older)",
+                search.query,
+                search.current + 1,
+                matches
+            )
+        }
+    } else if let Startup::Waiting(_) = app.startup {
+        format!("starting… {}/{} ready", app.ready_nodes(), app.status.len())
+    } else if let Startup::TimedOut(timeout) = app.startup {
+        let timed_out = format!(
+            "{}/{} ready, not all nodes answered within {}s",
+            app.ready_nodes(),
+            app.status.len(),
+            timeout.as_secs()
+        );
+        // the notice stays visible while the split is open.
+        match app.split {
+            Some(_) => format!("{} | Tab: switch pane, s: close split", timed_out),
+            None => timed_out,
+        }
+    } else if app.split.is_some() {
+        "Tab: switch pane, s: close split".to_string()
+    } else {
+        format!(
+            "network up, {}/{} ready",
+            app.ready_nodes(),
+            app.status.len()
+        )
+    };
+    f.render_widget(Paragraph::new(status), chunks[2]);
+    Ok(())
+}
+
+/// The log shown in `tab` and its title.
+fn tab_log<'l>(
+    app: &App,
+    tab: usize,
+    logs: &'l [String],
+    merged_log: &'l str,
+) -> anyhow::Result<(&'l str, String)> {
+    match tab.checked_sub(ALL_TAB + 1) {
+        Some(i) => Ok((
+            logs.get(i)
+                .context("no log for the selected node")?
+                .as_str(),
+            format!("Node {:?}", i),
+        )),
+        None => Ok((merged_log, app.titles[ALL_TAB].to_string())),
+    }
+}
+
+/// The log of `tab`, or why its node could not be spawned.
+fn view_tab(
+    app: &App,
+    tab: usize,
+    logs: &[String],
+    merged_log: &str,
+) -> anyhow::Result<Paragraph<'static>> {
+    let (log, title) = tab_log(app, tab, logs, merged_log)?;
+    let error = tab
+        .checked_sub(ALL_TAB + 1)
+        .and_then(|i| app.status[i].error.as_ref());
+    match error {
+        Some(error) => Ok(Paragraph::new(error.clone())
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!("{} failed to start (r: retry)", title)),
+            )
+            .style(Style::default().fg(Color::Red))
+            .wrap(Wrap { trim: false })),
+        None => view_log(
+            log.to_string(),
+            &title,
+            app.offsets[tab],
+            app.color_logs,
+            app.searches[tab]
+                .as_ref()
+                .map(|search| search.query.as_str()),
+        ),
+    }
+}
+
+/// Drop the oldest lines of `log` so that at most `max_lines` are left.
+fn trim_log(log: &mut String, max_lines: usize) {
+    let lines = log.matches('\n').count();
+    if lines <= max_lines {
+        return;
+    }
+    if let Some((end, _)) = log.match_indices('\n').nth(lines - max_lines - 1) {
+        log.drain(..=end);
+    }
+}
+
+/// The indices of the lines in `log` that contain `query`, ignoring case.
+fn find_matches(log: &str, query: &str) -> Vec<usize> {
+    let query = query.to_lowercase();
+    log.lines()
+        .enumerate()
+        .filter(|(_, line)| line.to_lowercase().contains(&query))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Interleave the lines of all nodes by the timestamps they start with,
+/// prefixing each line with the node it is from. Lines without a timestamp
+/// stay after the line before them.
+fn merge_logs(logs: &[String]) -> String {
+    let mut lines = vec![];
+    for (node, log) in logs.iter().enumerate() {
+        let mut timestamp = None;
+        for line in log.lines() {
+            if let Some(ts) = line
+                .split_whitespace()
+                .next()
+                .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+            {
+                timestamp = Some(ts);
+            }
+            lines.push((timestamp, node, line));
+        }
+    }
+    // the sort is stable so lines of a node keep their order.
+    lines.sort_by_key(|&(timestamp, node, _)| (timestamp, node));
+    let mut merged = String::new();
+    for (_, node, line) in lines {
+        merged.push_str(&format!("[node {}] {}\n", node, line));
+    }
+    merged
+}
+
+/// Style the severity level of a log line, ERROR in red and WARN in yellow.
+fn color_log_line(line: &str) -> Spans<'static> {
+    let level = line.split_whitespace().find_map(|token| {
+        let color = match token.trim_matches(|c: char| !c.is_ascii_alphabetic()) {
+            "ERROR" => Color::Red,
+            "WARN" | "WARNING" => Color::Yellow,
+            _ => return None,
+        };
+        Some((token, color))
+    });
+    match level.and_then(|(token, color)| Some((line.find(token)?, token, color))) {
+        Some((start, token, color)) => {
+            let end = start + token.len();
+            Spans::from(vec![
+                Span::raw(line[..start].to_string()),
+                Span::styled(
+                    token.to_string(),
+                    Style::default().fg(color).add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(line[end..].to_string()),
+            ])
+        }
+        None => Spans::from(line.to_string()),
+    }
+}
+
+/// The range of the lines of a log with `total` lines that is shown when it
+/// is scrolled up `offset` lines.
+fn visible_window(total: usize, offset: usize) -> (usize, usize) {
+    // the window ends `offset` lines above the newest line, clamped to the top of the log.
+    let end = total.saturating_sub(offset).max(LOG_VIEW_LINES.min(total));
+    (end.saturating_sub(LOG_VIEW_LINES), end)
+}
+
+fn view_log(
+    line: String,
+    title: &str,
+    offset: usize,
+    color: bool,
+    query: Option<&str>,
+) -> anyhow::Result<Paragraph<'static>> {
+    let lines: Vec<_> = line.lines().collect();
+    let total = lines.len();
+    let (start, end) = visible_window(total, offset);
+    let query = query.map(str::to_lowercase);
+    let to_show: Vec<_> = lines[start..end]
+        .iter()
+        .map(|line| {
+            let mut spans = if color {
+                color_log_line(line)
+            } else {
+                Spans::from(line.to_string())
+            };
+            // highlight the lines matching the search.
+            if let Some(query) = &query {
+                if line.to_lowercase().contains(query) {
+                    for span in &mut spans.0 {
+                        span.style = span.style.bg(Color::Yellow);
+                    }
+                }
+            }
+            spans
+        })
+        .collect();
+
+    Ok(Paragraph::new(Text::from(to_show))
+        .style(Style::default().bg(Color::White).fg(Color::Black))
+        .alignment(Alignment::Left)
+        .wrap(Wrap { trim: true })
+        .block(
+            Block::default()
+                .title(format!("{} [{}-{}/{}]", title, start, end, total))
+                .borders(Borders::ALL),
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_logs_of_no_lines_is_empty() {
+        assert_eq!(merge_logs(&[]), "");
+        assert_eq!(merge_logs(&[String::new(), String::new()]), "");
+    }
+
+    #[test]
+    fn merge_logs_interleaves_by_timestamp() {
+        let logs = [
+            "2022-05-22T10:00:01Z b\ncontinued\n2022-05-22T10:00:03Z d\n".to_string(),
+            "2022-05-22T10:00:00Z a\n2022-05-22T10:00:02Z c\n".to_string(),
+        ];
+        assert_eq!(
+            merge_logs(&logs),
+            "[node 1] 2022-05-22T10:00:00Z a\n\
+             [node 0] 2022-05-22T10:00:01Z b\n\
+             [node 0] continued\n\
+             [node 1] 2022-05-22T10:00:02Z c\n\
+             [node 0] 2022-05-22T10:00:03Z d\n"
+        );
+    }
+
+    /// An app with a tab per node in `titles`, showing the log of `node`.
+    fn app(titles: &[String], node: usize) -> App<'_> {
+        let mut app = App::new(titles, false, 10, Duration::from_secs(1));
+        app.index = node + ALL_TAB + 1;
+        app
+    }
+
+    #[test]
+    fn find_matches_ignores_case() {
+        assert!(find_matches("", "block").is_empty());
+        assert_eq!(find_matches("Block 1\nfinal\nBLOCK 2\n", "block"), [0, 2]);
+    }
+
+    #[test]
+    fn search_cycles_around_the_matches() {
+        let titles = ["Node 0".to_string(), "Node 1".to_string()];
+        let mut app = app(&titles, 1);
+        let log = "a\nmatch\nb\nmatch\nc\n";
+        app.search("match".to_string(), log);
+        // the search starts at the newest match.
+        assert_eq!(app.searches[app.index].as_ref().unwrap().current, 1);
+        app.cycle_match(log, true);
+        assert_eq!(app.searches[app.index].as_ref().unwrap().current, 0);
+        app.cycle_match(log, false);
+        assert_eq!(app.searches[app.index].as_ref().unwrap().current, 1);
+    }
+
+    #[test]
+    fn search_without_matches_keeps_the_offset() {
+        let titles = ["Node 0".to_string(), "Node 1".to_string()];
+        let mut app = app(&titles, 0);
+        app.offsets[app.index] = 3;
+        app.search("missing".to_string(), "");
+        app.cycle_match("", true);
+        assert_eq!(app.searches[app.index].as_ref().unwrap().current, 0);
+        assert_eq!(app.offsets[app.index], 3);
+    }
+
+    #[test]
+    fn trim_log_keeps_the_newest_lines() {
+        let mut log = String::new();
+        trim_log(&mut log, 2);
+        assert_eq!(log, "");
+        let mut log = "a\nb\nc\n".to_string();
+        trim_log(&mut log, 3);
+        assert_eq!(log, "a\nb\nc\n");
+        trim_log(&mut log, 2);
+        assert_eq!(log, "b\nc\n");
+        trim_log(&mut log, 0);
+        assert_eq!(log, "");
+    }
+
+    #[test]
+    fn visible_window_of_an_empty_log() {
+        assert_eq!(visible_window(0, 0), (0, 0));
+        assert_eq!(visible_window(0, 5), (0, 0));
+    }
+
+    #[test]
+    fn visible_window_after_trimming_below_the_offset() {
+        let total = LOG_VIEW_LINES * 3;
+        assert_eq!(visible_window(total, 0), (total - LOG_VIEW_LINES, total));
+        assert_eq!(
+            visible_window(total, LOG_VIEW_LINES),
+            (LOG_VIEW_LINES, 2 * LOG_VIEW_LINES)
+        );
+        // the log was trimmed to fewer lines than it is scrolled up, so the
+        // oldest lines are shown.
+        assert_eq!(visible_window(total, total * 2), (0, LOG_VIEW_LINES));
+        assert_eq!(visible_window(10, 50), (0, 10));
+    }
+}
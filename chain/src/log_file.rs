@@ -0,0 +1,129 @@
+use anyhow::Context;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The number of rolled segments kept next to the current log file.
+const ROLLED_SEGMENTS: usize = 2;
+
+/// A log file that is optionally rotated once it exceeds a maximum size. The
+/// rolled segments are named `<file>.1` (the newest) up to `<file>.2`.
+pub(crate) struct LogFile {
+    path: PathBuf,
+    file: File,
+    /// The number of bytes in the current segment.
+    written: u64,
+    /// Rotate when the current segment would exceed this many bytes.
+    max_bytes: Option<u64>,
+}
+
+impl LogFile {
+    /// Open the log file at `path`, either appending to or truncating an
+    /// existing file.
+    pub(crate) fn open(
+        path: PathBuf,
+        append: bool,
+        max_bytes: Option<u64>,
+    ) -> anyhow::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&path)
+            .context(format!("cannot create log file {}", path.display()))?;
+        // a truncated file starts empty, an appended one with its contents.
+        let written = if append { file.metadata()?.len() } else { 0 };
+        Ok(LogFile {
+            path,
+            file,
+            written,
+            max_bytes,
+        })
+    }
+
+    /// Write `buf`, rotating the file first if it would grow too large.
+    pub(crate) fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        if let Some(max_bytes) = self.max_bytes {
+            if self.written > 0 && self.written + buf.len() as u64 > max_bytes {
+                self.rotate()?;
+            }
+        }
+        self.file.write_all(buf)?;
+        self.written += buf.len() as u64;
+        Ok(())
+    }
+
+    /// Shift the rolled segments by one, dropping the oldest, and start a new
+    /// segment.
+    fn rotate(&mut self) -> std::io::Result<()> {
+        for n in (1..ROLLED_SEGMENTS).rev() {
+            let from = segment(&self.path, n);
+            if from.exists() {
+                std::fs::rename(from, segment(&self.path, n + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, segment(&self.path, 1))?;
+        self.file = File::create(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+/// The path of the `n`th rolled segment of the log file at `path`.
+fn segment(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for the log files of test `name`.
+    fn log_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("chain-log-file-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read(path: PathBuf) -> String {
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn rotation_keeps_two_rolled_segments() {
+        let dir = log_dir("rotate");
+        let path = dir.join("peer-0.log");
+        let mut log = LogFile::open(path.clone(), false, Some(10)).unwrap();
+        for n in 1..=4 {
+            log.write_all(format!("segment{}", n).as_bytes()).unwrap();
+        }
+        assert_eq!(read(path.clone()), "segment4");
+        assert_eq!(read(segment(&path, 1)), "segment3");
+        assert_eq!(read(segment(&path, 2)), "segment2");
+        assert!(!segment(&path, 3).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_appended_files_count_their_contents() {
+        let dir = log_dir("append");
+        let path = dir.join("peer-0.log");
+        std::fs::write(&path, "old lines").unwrap();
+        // the truncated file has room for the first write.
+        let mut log = LogFile::open(path.clone(), false, Some(10)).unwrap();
+        log.write_all(b"new").unwrap();
+        assert_eq!(read(path.clone()), "new");
+        assert!(!segment(&path, 1).exists());
+        // the appended file already holds 3 bytes, so another 8 rotate it.
+        let mut log = LogFile::open(path.clone(), true, Some(10)).unwrap();
+        log.write_all(b"appended").unwrap();
+        assert_eq!(read(path.clone()), "appended");
+        assert_eq!(read(segment(&path, 1)), "new");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,86 @@
+use crate::config::LogSink;
+use anyhow::Context;
+
+/// The socket syslog reads its messages from.
+#[cfg(unix)]
+const SYSLOG_SOCKET: &str = "/dev/log";
+/// The socket of the native protocol of systemd-journald.
+#[cfg(unix)]
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Sends the lines of the log of a node to syslog or journald, tagged with
+/// the node as `chain-node-N`.
+pub(crate) struct SinkWriter {
+    #[cfg(unix)]
+    socket: std::os::unix::net::UnixDatagram,
+    sink: LogSink,
+    node: usize,
+    pid: u32,
+}
+
+impl SinkWriter {
+    /// Connect to the `sink` for node `i`, which runs as process `pid`. There
+    /// is nothing to connect to for files, which are opened with the log file.
+    #[cfg(unix)]
+    pub(crate) fn connect(sink: LogSink, i: usize, pid: u32) -> anyhow::Result<Option<SinkWriter>> {
+        let path = match sink {
+            LogSink::File => return Ok(None),
+            LogSink::Syslog => SYSLOG_SOCKET,
+            LogSink::Journald => JOURNALD_SOCKET,
+        };
+        let socket = std::os::unix::net::UnixDatagram::unbound()?;
+        socket
+            .connect(path)
+            .context(format!("cannot connect to {}", path))?;
+        Ok(Some(SinkWriter {
+            socket,
+            sink,
+            node: i,
+            pid,
+        }))
+    }
+
+    /// Syslog and journald are only reached over Unix sockets.
+    #[cfg(not(unix))]
+    pub(crate) fn connect(
+        sink: LogSink,
+        _i: usize,
+        _pid: u32,
+    ) -> anyhow::Result<Option<SinkWriter>> {
+        match sink {
+            LogSink::File => Ok(None),
+            _ => anyhow::bail!("--log-sink {} is only supported on Unix", sink),
+        }
+    }
+
+    /// Send every line of `lines` as a message of its own.
+    pub(crate) fn write_lines(&mut self, lines: &str) -> std::io::Result<()> {
+        for line in lines.lines().filter(|line| !line.is_empty()) {
+            self.send(&self.message(line))?;
+        }
+        Ok(())
+    }
+
+    /// The `line` in the format of the sink, with the priority info.
+    fn message(&self, line: &str) -> String {
+        let tag = format!("chain-node-{}", self.node);
+        match self.sink {
+            // facility user (1) and severity info (6).
+            LogSink::Syslog | LogSink::File => format!("<14>{}[{}]: {}", tag, self.pid, line),
+            LogSink::Journald => format!(
+                "MESSAGE={}\nPRIORITY=6\nSYSLOG_IDENTIFIER={}\nSYSLOG_PID={}\nCHAIN_NODE={}\n",
+                line, tag, self.pid, self.node
+            ),
+        }
+    }
+
+    #[cfg(unix)]
+    fn send(&self, message: &str) -> std::io::Result<()> {
+        self.socket.send(message.as_bytes()).map(drop)
+    }
+
+    #[cfg(not(unix))]
+    fn send(&self, _message: &str) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,596 @@
+use crate::config::LogSink;
+use crate::latency::check_latencies;
+use crate::log_file::LogFile;
+use crate::log_sink::SinkWriter;
+use crate::topology::Topology;
+use crate::Config;
+use anyhow::Context;
+use std::io::{BufRead, BufReader, Read};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// How to create the genesis data, appended to the errors about missing files.
+const GENESIS_HINT: &str = "Run generate-test-genesis.py from deps/concordium-node/scripts/genesis to create the genesis data, or point --genesis-root to it";
+
+/// The paths and wiring shared by all nodes of the network, resolved once from
+/// the config.
+pub(crate) struct Network<'a> {
+    pub(crate) cfg: &'a Config,
+    pub(crate) genesis_root: PathBuf,
+    /// The manifest of the node, used unless a prebuilt binary is given.
+    pub(crate) node_manifest: PathBuf,
+    pub(crate) node_binary: Option<PathBuf>,
+    pub(crate) topology: Option<Topology>,
+    pub(crate) ports: Vec<NodePorts>,
+}
+
+/// The ports a node listens on.
+#[derive(Clone, Copy)]
+pub(crate) struct NodePorts {
+    /// The port of the gRPC interface.
+    pub(crate) rpc: u16,
+    /// The port for connections from other peers.
+    pub(crate) peer: u16,
+}
+
+impl<'a> Network<'a> {
+    pub(crate) fn new(cfg: &'a Config) -> anyhow::Result<Network<'a>> {
+        let genesis_root = genesis_root(cfg)?;
+        anyhow::ensure!(
+            [cfg.optimal_connected, cfg.ring, cfg.topology.is_some()]
+                .iter()
+                .filter(|&&layout| layout)
+                .count()
+                <= 1,
+            "only one of optimal-connected, ring and topology can be chosen"
+        );
+        anyhow::ensure!(
+            cfg.tui_buffer_lines > 0,
+            "the ui must keep at least one line of the logs"
+        );
+        anyhow::ensure!(
+            cfg.log_batch_lines > 0,
+            "--log-batch-lines must be at least 1"
+        );
+        anyhow::ensure!(
+            cfg.num_bakers() <= cfg.num_nodes,
+            "cannot have {} bakers with only {} nodes",
+            cfg.num_bakers(),
+            cfg.num_nodes
+        );
+        let topology = if let Some(path) = &cfg.topology {
+            Some(Topology::from_file(path, cfg.num_nodes)?)
+        } else if cfg.ring {
+            Some(Topology::ring(cfg.num_nodes))
+        } else {
+            None
+        };
+        let ports = if cfg.auto_ports {
+            free_ports(cfg.num_nodes)?
+        } else {
+            configured_ports(cfg)?
+        };
+        let node_binary = match &cfg.node_binary {
+            Some(binary) => Some(binary.canonicalize().context(format!(
+                "cannot find the node binary at {}",
+                binary.display()
+            ))?),
+            None => None,
+        };
+        // the manifest is only needed when the node is built with cargo.
+        let node_manifest = if node_binary.is_none() {
+            node_manifest(cfg)?
+        } else {
+            cfg.node_manifest.clone()
+        };
+        let network = Network {
+            cfg,
+            genesis_root,
+            node_manifest,
+            node_binary,
+            topology,
+            ports,
+        };
+        // check the files up front so no node is spawned if one is missing.
+        if !cfg.continue_state {
+            network.genesis_dat()?;
+        }
+        for i in 0..cfg.num_nodes {
+            if let Some(baker) = network.baker_id(i) {
+                network.baker_credentials(baker)?;
+            }
+        }
+        // the latencies are ignored where there is no netem.
+        if cfg!(target_os = "linux") {
+            check_latencies(&network)?;
+        }
+        Ok(network)
+    }
+
+    /// The baker credentials node `i` uses, if it is a baker.
+    pub(crate) fn baker_id(&self, i: usize) -> Option<usize> {
+        baker_id(self.cfg, i)
+    }
+
+    /// The latency in milliseconds of the p2p traffic of node `i`. The latency
+    /// of the edges to the node in the topology replaces `--latency-ms`.
+    pub(crate) fn latency_ms(&self, i: usize) -> Option<u64> {
+        self.topology
+            .as_ref()
+            .and_then(|topology| topology.latency_to(i))
+            .or(self.cfg.latency_ms)
+    }
+
+    /// The nodes that node `i` connects to.
+    fn connect_to(&self, i: usize) -> Vec<usize> {
+        let cfg = self.cfg;
+        if let Some(topology) = &self.topology {
+            topology.peers(i).collect()
+        } else if !cfg.optimal_connected {
+            // in a line every node connects to the next one.
+            (i + 1..cfg.num_nodes).take(1).collect()
+        } else {
+            (i + 1..cfg.num_nodes).collect()
+        }
+    }
+
+    /// Write the connections between the nodes as a Graphviz DOT graph, with
+    /// bakers drawn as boxes.
+    pub(crate) fn write_dot(&self, path: &Path) -> anyhow::Result<()> {
+        let mut dot = String::from("digraph network {\n");
+        for i in 0..self.cfg.num_nodes {
+            match self.baker_id(i) {
+                Some(baker) => dot.push_str(&format!(
+                    "    {} [label=\"node {}\\nbaker {}\", shape=box];\n",
+                    i, i, baker
+                )),
+                None => dot.push_str(&format!("    {} [label=\"node {}\"];\n", i, i)),
+            }
+        }
+        for i in 0..self.cfg.num_nodes {
+            for n in self.connect_to(i) {
+                dot.push_str(&format!("    {} -> {};\n", i, n));
+            }
+        }
+        dot.push_str("}\n");
+        std::fs::write(path, dot)
+            .context(format!("cannot write the topology to {}", path.display()))
+    }
+
+    fn baker_credentials(&self, baker: usize) -> anyhow::Result<PathBuf> {
+        baker_credentials(&self.genesis_root, baker)
+    }
+
+    /// The genesis block the data directories of new nodes start from.
+    pub(crate) fn genesis_dat(&self) -> anyhow::Result<PathBuf> {
+        genesis_dat(&self.genesis_root)
+    }
+
+    /// Build the command for running node `i`.
+    /// The node keeps using the data directory `peer-i`.
+    pub(crate) fn node_command(&self, i: usize) -> anyhow::Result<Command> {
+        let cfg = self.cfg;
+        // command for running the node
+        let mut cmd = match &self.node_binary {
+            Some(binary) => Command::new(binary),
+            None => {
+                let mut cmd = Command::new("cargo");
+                cmd.arg("run");
+                cmd.arg("--manifest-path").arg(&self.node_manifest);
+                cmd.arg("--release");
+                cmd.arg("--quiet");
+                cmd.arg("--");
+                cmd
+            }
+        };
+        cmd.env("RUST_BACKTRACE", "full");
+        let node_cfg = cfg.node(i);
+        cmd.env(
+            "CONCORDIUM_NODE_RUNTIME_HASKELL_RTS_FLAGS",
+            node_cfg.rts_flags.as_ref().unwrap_or(&cfg.rts_flags),
+        );
+        cmd.env("CONCORDIUM_NODE_CONNECTION_NO_BOOTSTRAP_DNS", "1");
+        cmd.env("CONCORDIUM_NODE_ID", format!("{:016x}", i as u64).as_str());
+        cmd.env(
+            "CONCORDIUM_NODE_CONFIG_DIR",
+            format!("peer-{:?}", i).as_str(),
+        );
+        cmd.env("CONCORDIUM_NODE_DATA_DIR", format!("peer-{:?}", i).as_str());
+        cmd.env(
+            "CONCORDIUM_NODE_RPC_SERVER_PORT",
+            format!("{}", self.ports[i].rpc).as_str(),
+        );
+        cmd.env(
+            "CONCORDIUM_NODE_LISTEN_PORT",
+            format!("{}", self.ports[i].peer).as_str(),
+        );
+        cmd.env("CONCORDIUM_NODE_LISTEN_ADDRESS", "0.0.0.0");
+        cmd.env(
+            "CONCORDIUM_NODE_CONNECTION_HOUSEKEEPING_INTERVAL",
+            format!("{}", cfg.housekeeping_interval).as_str(),
+        );
+        cmd.env(
+            "CONCORDIUM_NODE_MAX_NORMAL_KEEP_ALIVE",
+            format!("{}", cfg.housekeeping_interval * 3).as_str(),
+        );
+        cmd.env(
+            "CONCORDIUM_NODE_CONSENSUS_ACCOUNTS_CACHE_SIZE",
+            format!("{}", cfg.accounts_cache_size),
+        );
+
+        if let Some(level) = cfg.node_log_level {
+            cmd.arg(level.flag());
+        }
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+
+        if let Some(baker) = self.baker_id(i) {
+            let baker_credentials = self.baker_credentials(baker)?;
+            cmd.env(
+                "CONCORDIUM_NODE_BAKER_CREDENTIALS_FILE",
+                baker_credentials.to_str().unwrap().to_string().as_str(),
+            );
+        }
+
+        if let Some(topology) = &self.topology {
+            // the nodes are connected as described by the topology file or in a ring.
+            let connect_to: Vec<_> = self
+                .connect_to(i)
+                .into_iter()
+                .map(|n| format!("127.0.0.1:{}", self.ports[n].peer))
+                .collect();
+            if !connect_to.is_empty() {
+                cmd.env(
+                    "CONCORDIUM_NODE_CONNECTION_CONNECT_TO",
+                    connect_to.join(","),
+                );
+            }
+            // nodes without any edges only listen for connections.
+            let degree = topology.degree(i);
+            if degree > 0 {
+                cmd.env(
+                    "CONCORDIUM_NODE_CONNECTION_DESIRED_NODES",
+                    format!("{}", degree).as_str(),
+                );
+                cmd.env(
+                    "CONCORDIUM_NODE_CONNECTION_MAX_ALLOWED_NODES",
+                    format!("{}", degree).as_str(),
+                );
+            }
+        } else if !cfg.optimal_connected {
+            // the nodes will be connected sequentially
+            // we submit transactions at the start of the queue.
+            // O - O - O - O - B
+
+            // if the node is last in line we don't connect to the one behind us.
+            // we're the first peer in line so we only connect to the peer in front of us.
+            for n in self.connect_to(i) {
+                cmd.env(
+                    "CONCORDIUM_NODE_CONNECTION_CONNECT_TO",
+                    format!("127.0.0.1:{}", self.ports[n].peer),
+                );
+            }
+
+            // if the node is either at the start or at the end it should only be connected one other peer
+            if i == 0 || i == cfg.num_nodes - 1 {
+                cmd.env(
+                    "CONCORDIUM_NODE_CONNECTION_DESIRED_NODES",
+                    format!("{}", 1).as_str(),
+                );
+                cmd.env(
+                    "CONCORDIUM_NODE_CONNECTION_MAX_ALLOWED_NODES",
+                    format!("{}", 1).as_str(),
+                );
+            } else {
+                // else the peer will be connected to the peer at 'each side' of it.
+                cmd.env(
+                    "CONCORDIUM_NODE_CONNECTION_DESIRED_NODES",
+                    format!("{}", 2).as_str(),
+                );
+                cmd.env(
+                    "CONCORDIUM_NODE_CONNECTION_MAX_ALLOWED_NODES",
+                    format!("{}", 2).as_str(),
+                );
+            }
+        } else {
+            for n in self.connect_to(i) {
+                cmd.args([
+                    "--connect-to",
+                    format!("127.0.0.1:{}", self.ports[n].peer).as_str(),
+                ]);
+            }
+        }
+        cmd.envs(&node_cfg.env);
+
+        Ok(cmd)
+    }
+
+    /// Launch node `i` again with the same command, keeping its data
+    /// directory. Its process in `fork` must have been shut down.
+    pub(crate) fn restart_node(
+        &self,
+        i: usize,
+        fork: &mut Option<Child>,
+        sender: tokio::sync::mpsc::Sender<String>,
+    ) -> anyhow::Result<()> {
+        *fork = None;
+        let mut cmd = self.node_command(i)?;
+        *fork = Some(spawn_node(
+            i,
+            &mut cmd,
+            log_file_or_warn(i, self.cfg, true, &sender),
+            self.cfg.log_sink,
+            self.cfg.log_batch_lines,
+            sender,
+        )?);
+        Ok(())
+    }
+}
+
+/// Find free ports for `num_nodes` nodes by letting the OS assign them.
+/// All sockets are kept open until every port is chosen so no port is handed
+/// out twice.
+fn free_ports(num_nodes: usize) -> anyhow::Result<Vec<NodePorts>> {
+    let mut listeners = vec![];
+    let mut ports = vec![];
+    for _ in 0..num_nodes {
+        let rpc = TcpListener::bind("127.0.0.1:0").context("cannot find a free port")?;
+        let peer = TcpListener::bind("0.0.0.0:0").context("cannot find a free port")?;
+        ports.push(NodePorts {
+            rpc: rpc.local_addr()?.port(),
+            peer: peer.local_addr()?.port(),
+        });
+        listeners.push(rpc);
+        listeners.push(peer);
+    }
+    Ok(ports)
+}
+
+/// The directory with the genesis data, from `--genesis-root`.
+pub(crate) fn genesis_root(cfg: &Config) -> anyhow::Result<PathBuf> {
+    let genesis_root = PathBuf::from(&cfg.genesis_root);
+    genesis_root.canonicalize().context(format!(
+        "cannot find the genesis root at {}. {}",
+        std::path::absolute(&genesis_root)
+            .unwrap_or(genesis_root)
+            .display(),
+        GENESIS_HINT
+    ))
+}
+
+pub(crate) fn genesis_dat(genesis_root: &Path) -> anyhow::Result<PathBuf> {
+    let path = genesis_root.join("genesis.dat");
+    path.canonicalize().context(format!(
+        "cannot find genesis.dat at {}. {}",
+        path.display(),
+        GENESIS_HINT
+    ))
+}
+
+pub(crate) fn baker_credentials(genesis_root: &Path, baker: usize) -> anyhow::Result<PathBuf> {
+    let path = genesis_root.join(format!("bakers/baker-{}-credentials.json", baker));
+    path.canonicalize().context(format!(
+        "cannot find the credentials of baker {} at {}. {}",
+        baker,
+        path.display(),
+        GENESIS_HINT
+    ))
+}
+
+/// The manifest the node is built and run with by cargo.
+pub(crate) fn node_manifest(cfg: &Config) -> anyhow::Result<PathBuf> {
+    cfg.node_manifest.canonicalize().context(format!(
+        "cannot find the node manifest at {}. Use --node-manifest to point to concordium-node/Cargo.toml",
+        cfg.node_manifest.display()
+    ))
+}
+
+/// The ports of the nodes counted up from `--rpc-port-offset` and
+/// `--peer-port-offset`.
+pub(crate) fn configured_ports(cfg: &Config) -> anyhow::Result<Vec<NodePorts>> {
+    (0..cfg.num_nodes)
+        .map(|i| {
+            Ok(NodePorts {
+                rpc: u16::try_from(cfg.rpc_port_offset + i)?,
+                peer: u16::try_from(cfg.peer_port_offset + i)?,
+            })
+        })
+        .collect::<anyhow::Result<_>>()
+        .context("port out of range")
+}
+
+/// The baker credentials node `i` uses, if it is a baker.
+pub(crate) fn baker_id(cfg: &Config, i: usize) -> Option<usize> {
+    cfg.node(i).baker.or_else(|| default_baker_id(i, cfg))
+}
+
+/// The baker credentials node `i` uses unless overridden in the config file.
+fn default_baker_id(i: usize, cfg: &Config) -> Option<usize> {
+    let num_bakers = cfg.num_bakers();
+    if cfg.is_line() {
+        // assign the last nodes to be bakers, counting from the end of the line.
+        let from_end = cfg.num_nodes - 1 - i;
+        (from_end < num_bakers).then_some(from_end)
+    } else {
+        // assign the first nodes to be bakers
+        (i < num_bakers).then_some(i)
+    }
+}
+
+/// Open the log file of node `i`. Logs in the log directory are always
+/// written and rotated, otherwise `peer-i.log` is written unless logs are
+/// disabled.
+fn open_log_file(i: usize, cfg: &Config, append: bool) -> anyhow::Result<Option<LogFile>> {
+    let file_name = format!("peer-{}.log", i);
+    let fh = match &cfg.log_dir {
+        Some(log_dir) => {
+            std::fs::create_dir_all(log_dir)
+                .context(format!("cannot create log directory {}", log_dir.display()))?;
+            let max_bytes = cfg.log_max_mb * 1024 * 1024;
+            LogFile::open(log_dir.join(file_name), append, Some(max_bytes))
+        }
+        None if cfg.no_emit_logs || cfg.log_sink != LogSink::File => return Ok(None),
+        None => LogFile::open(PathBuf::from(file_name), append, None),
+    }
+    .context(format!("cannot create log file for peer {}", i))?;
+    Ok(Some(fh))
+}
+
+/// Open the log file of node `i` like `open_log_file`. A node whose log file
+/// cannot be created is still spawned, with a warning at the top of its log
+/// on `sender`, and its log is then only forwarded and not written to disk.
+pub(crate) fn log_file_or_warn(
+    i: usize,
+    cfg: &Config,
+    append: bool,
+    sender: &tokio::sync::mpsc::Sender<String>,
+) -> Option<LogFile> {
+    open_log_file(i, cfg, append).unwrap_or_else(|e| {
+        let _ = sender.try_send(format!(
+            "--- warning: {:#}, the log of the node is not written to disk ---\n",
+            e
+        ));
+        None
+    })
+}
+
+/// Spawn node `i` and forward its stderr and stdout, interleaved line by line,
+/// to `sender`, the log file `fh` and the `log_sink`. The lines that are available are
+/// forwarded at once, at most `batch_lines` of them.
+pub(crate) fn spawn_node(
+    i: usize,
+    cmd: &mut Command,
+    mut fh: Option<LogFile>,
+    log_sink: LogSink,
+    batch_lines: usize,
+    sender: tokio::sync::mpsc::Sender<String>,
+) -> anyhow::Result<Child> {
+    let mut fork = cmd
+        .spawn()
+        .context(format!("Failed to launch node {:?}", i))?;
+    // like a log file that cannot be created, an unreachable sink does not
+    // stop the node.
+    let mut sink = SinkWriter::connect(log_sink, i, fork.id()).unwrap_or_else(|e| {
+        let _ = sender.try_send(format!(
+            "--- warning: cannot write the log to {}: {:#} ---\n",
+            log_sink.to_string().to_lowercase(),
+            e
+        ));
+        None
+    });
+    let (line_sender, mut lines) = tokio::sync::mpsc::channel(100);
+    forward_lines(
+        i,
+        fork.stderr.take().context("Could not take stderr")?,
+        line_sender.clone(),
+    );
+    forward_lines(
+        i,
+        fork.stdout.take().context("Could not take stdout")?,
+        line_sender,
+    );
+    // the reader stops when the node closes both pipes, which drops the
+    // sender and thereby closes the channel.
+    let reader = async move {
+        while let Some(line) = lines.recv().await {
+            let mut buffered_line = line;
+            for _ in 1..batch_lines {
+                match lines.try_recv() {
+                    Ok(line) => buffered_line.push_str(&line),
+                    Err(_) => break,
+                }
+            }
+            // write to log file if enabled
+            if let Some(ref mut log_file) = fh {
+                if let Err(e) = log_file.write_all(buffered_line.as_bytes()) {
+                    buffered_line
+                        .push_str(&format!("Failed to write log file of node {}: {}\n", i, e));
+                    fh = None;
+                }
+            }
+            if let Some(ref mut writer) = sink {
+                if let Err(e) = writer.write_lines(&buffered_line) {
+                    buffered_line.push_str(&format!(
+                        "Failed to write the log of node {} to {}: {}\n",
+                        i,
+                        log_sink.to_string().to_lowercase(),
+                        e
+                    ));
+                    sink = None;
+                }
+            }
+            // send to ui, this only fails if the ui has stopped listening.
+            if sender.send(buffered_line).await.is_err() {
+                break;
+            }
+        }
+    };
+    tokio::spawn(reader);
+    Ok(fork)
+}
+
+/// Read the lines of a `pipe` of node `i` on a thread of its own and send them
+/// to `lines`. Every pipe is read on its own, so a pipe that fills up while
+/// the other one is read never blocks the node.
+fn forward_lines(
+    i: usize,
+    pipe: impl Read + Send + 'static,
+    lines: tokio::sync::mpsc::Sender<String>,
+) {
+    std::thread::spawn(move || {
+        let mut buf_reader = BufReader::new(pipe);
+        loop {
+            // the lines are read as bytes, so that a line that is not valid
+            // UTF-8 is forwarded with replacement characters instead of
+            // stopping the reads, which would leave the node blocked on a
+            // full pipe.
+            let mut bytes = vec![];
+            let (line, eof) = match buf_reader.read_until(b'\n', &mut bytes) {
+                Ok(0) => (String::new(), true),
+                Ok(_) => (String::from_utf8_lossy(&bytes).into_owned(), false),
+                // the pipe itself cannot be read anymore.
+                Err(e) => (format!("Failed to read log of node {}: {}\n", i, e), true),
+            };
+            // this only fails if the reader has stopped.
+            if (!line.is_empty() && lines.blocking_send(line).is_err()) || eof {
+                break;
+            }
+        }
+    });
+}
+
+/// Ask the running nodes to terminate so they can flush their databases, and
+/// kill the ones that have not exited within the configured grace period.
+/// Nodes that failed to spawn have no process and are skipped.
+pub(crate) fn shutdown_nodes(forks: &mut [Option<Child>], cfg: &Config) -> anyhow::Result<()> {
+    #[cfg(unix)]
+    {
+        for fork in forks.iter_mut().flatten() {
+            if fork.try_wait()?.is_none() {
+                let pid = nix::unistd::Pid::from_raw(fork.id() as i32);
+                // if the signal cannot be sent the node is killed below.
+                let _ = nix::sys::signal::kill(pid, nix::sys::signal::Signal::SIGTERM);
+            }
+        }
+        let deadline = Instant::now() + Duration::from_secs(cfg.shutdown_grace_secs);
+        while Instant::now() < deadline {
+            let mut all_exited = true;
+            for fork in forks.iter_mut().flatten() {
+                all_exited &= fork.try_wait()?.is_some();
+            }
+            if all_exited {
+                return Ok(());
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+    for fork in forks.iter_mut().flatten() {
+        if fork.try_wait()?.is_none() {
+            fork.kill()?;
+            fork.wait()?;
+        }
+    }
+    Ok(())
+}
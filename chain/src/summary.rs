@@ -0,0 +1,74 @@
+use crate::node::Network;
+use crate::{Config, NodeStatus};
+use anyhow::Context;
+use serde::Serialize;
+use std::path::Path;
+
+/// The record of a session, written when the nodes are shut down.
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Summary<'a> {
+    started: String,
+    stopped: String,
+    /// The configuration the nodes were spawned with, including the config
+    /// file.
+    config: &'a Config,
+    nodes: Vec<NodeSummary>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct NodeSummary {
+    node: usize,
+    /// The pid of the last process of the node.
+    pid: Option<u32>,
+    rpc_port: u16,
+    p2p_port: u16,
+    /// The baker credentials the node used, if it is a baker.
+    baker: Option<usize>,
+    /// How long the processes of the node ran in total.
+    uptime_secs: f64,
+    restarts: u32,
+    /// How the last process of the node exited.
+    exit_status: Option<String>,
+    lines_logged: usize,
+    /// Why the node could not be spawned.
+    error: Option<String>,
+}
+
+/// Write the summary of the session that started at `started` to `path` as
+/// JSON, from the `status` of every node. The nodes must have been shut down.
+pub(crate) fn write_summary(
+    path: &Path,
+    status: &[NodeStatus],
+    network: &Network,
+    started: chrono::DateTime<chrono::Utc>,
+) -> anyhow::Result<()> {
+    let nodes = status
+        .iter()
+        .zip(&network.ports)
+        .enumerate()
+        .map(|(i, (status, ports))| NodeSummary {
+            node: i,
+            pid: status.pid,
+            rpc_port: ports.rpc,
+            p2p_port: ports.peer,
+            baker: network.baker_id(i),
+            uptime_secs: status.uptime().as_secs_f64(),
+            restarts: status.restarts,
+            exit_status: status.exit_status.clone(),
+            lines_logged: status.lines,
+            error: status.error.clone(),
+        })
+        .collect();
+    let summary = Summary {
+        started: started.to_rfc3339(),
+        stopped: chrono::Utc::now().to_rfc3339(),
+        config: network.cfg,
+        nodes,
+    };
+    let file = std::fs::File::create(path)
+        .context(format!("cannot create summary file {}", path.display()))?;
+    serde_json::to_writer_pretty(file, &summary)
+        .context(format!("cannot write summary file {}", path.display()))
+}
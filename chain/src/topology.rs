@@ -0,0 +1,175 @@
+use anyhow::Context;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// A network layout given as directed edges between nodes, where the edge
+/// `[a, b]` means that node `a` connects to node `b`.
+pub(crate) struct Topology {
+    edges: Vec<(usize, usize)>,
+    /// The latency in milliseconds of the edges that have one.
+    latencies: BTreeMap<(usize, usize), u64>,
+}
+
+/// An edge in a topology file, optionally with its latency in milliseconds.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Edge {
+    Delayed(usize, usize, u64),
+    Plain(usize, usize),
+}
+
+impl Topology {
+    /// Read the edges from a JSON file such as `[[0, 1], [1, 2, 50]]`, where
+    /// the optional third element is the latency of the edge in milliseconds.
+    pub(crate) fn from_file(path: &Path, num_nodes: usize) -> anyhow::Result<Topology> {
+        let contents = std::fs::read_to_string(path)
+            .context(format!("cannot read topology file {}", path.display()))?;
+        let file: Vec<Edge> = serde_json::from_str(&contents)
+            .context(format!("invalid topology file {}", path.display()))?;
+        let mut edges = vec![];
+        let mut latencies = BTreeMap::new();
+        for edge in file {
+            let (from, to) = match edge {
+                Edge::Delayed(from, to, latency) => {
+                    latencies.insert((from, to), latency);
+                    (from, to)
+                }
+                Edge::Plain(from, to) => (from, to),
+            };
+            anyhow::ensure!(
+                from < num_nodes && to < num_nodes,
+                "the edge [{}, {}] in {} refers to a node that is not spawned, there are only {} nodes",
+                from,
+                to,
+                path.display(),
+                num_nodes
+            );
+            anyhow::ensure!(from != to, "node {} cannot connect to itself", from);
+            edges.push((from, to));
+        }
+        Ok(Topology { edges, latencies })
+    }
+
+    /// A ring where every node connects to the next one, and the last node
+    /// connects to the first.
+    pub(crate) fn ring(num_nodes: usize) -> Topology {
+        let edges = (0..num_nodes)
+            .map(|i| (i, (i + 1) % num_nodes))
+            .filter(|(from, to)| from != to)
+            .collect();
+        Topology {
+            edges,
+            latencies: BTreeMap::new(),
+        }
+    }
+
+    /// The nodes that node `i` connects to.
+    pub(crate) fn peers(&self, i: usize) -> impl Iterator<Item = usize> + '_ {
+        self.edges
+            .iter()
+            .filter(move |(from, _)| *from == i)
+            .map(|(_, to)| *to)
+    }
+
+    /// The largest latency of the edges to node `i`, if any of them has one.
+    pub(crate) fn latency_to(&self, i: usize) -> Option<u64> {
+        self.latencies
+            .iter()
+            .filter(|((_, to), _)| *to == i)
+            .map(|(_, latency)| *latency)
+            .max()
+    }
+
+    /// The number of distinct nodes that node `i` is connected to in either
+    /// direction.
+    pub(crate) fn degree(&self, i: usize) -> usize {
+        self.edges
+            .iter()
+            .filter_map(|&(from, to)| {
+                if from == i {
+                    Some(to)
+                } else if to == i {
+                    Some(from)
+                } else {
+                    None
+                }
+            })
+            .collect::<BTreeSet<_>>()
+            .len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Read the topology `json` from a file, as given with --topology.
+    fn from_json(name: &str, json: &str, num_nodes: usize) -> anyhow::Result<Topology> {
+        let path = std::env::temp_dir().join(format!(
+            "chain-topology-{}-{}.json",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, json)?;
+        let topology = Topology::from_file(&path, num_nodes);
+        std::fs::remove_file(&path)?;
+        topology
+    }
+
+    #[test]
+    fn edges_with_and_without_latency() {
+        let topology = from_json("edges", "[[0, 1], [0, 2, 50], [1, 2, 20]]", 3).unwrap();
+        assert_eq!(topology.peers(0).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(topology.peers(2).count(), 0);
+        assert_eq!(topology.latency_to(1), None);
+        assert_eq!(topology.latency_to(2), Some(50));
+    }
+
+    #[test]
+    fn edges_to_unknown_nodes_are_errors() {
+        let e = from_json("unknown", "[[0, 3]]", 3).err().unwrap();
+        assert!(format!("{:#}", e).contains("there are only 3 nodes"));
+    }
+
+    #[test]
+    fn self_loops_are_errors() {
+        let e = from_json("loop", "[[0, 1], [2, 2]]", 3).err().unwrap();
+        assert!(format!("{:#}", e).contains("node 2 cannot connect to itself"));
+    }
+
+    #[test]
+    fn latencies_are_whole_milliseconds() {
+        for (name, json) in [
+            ("suffix", r#"[[0, 1, "50ms"]]"#),
+            ("negative", "[[0, 1, -5]]"),
+            ("fraction", "[[0, 1, 2.5]]"),
+            ("short", "[[0]]"),
+        ] {
+            let e = from_json(name, json, 2).err().unwrap();
+            assert!(
+                format!("{:#}", e).contains("invalid topology file"),
+                "{}",
+                json
+            );
+        }
+    }
+
+    #[test]
+    fn ring_connects_every_node_to_the_next() {
+        let ring = Topology::ring(3);
+        assert_eq!(ring.peers(0).collect::<Vec<_>>(), [1]);
+        assert_eq!(ring.peers(2).collect::<Vec<_>>(), [0]);
+        assert!((0..3).all(|i| ring.degree(i) == 2));
+        // a single node has nothing to connect to.
+        assert_eq!(Topology::ring(1).degree(0), 0);
+    }
+
+    #[test]
+    fn degree_counts_each_neighbour_once() {
+        let topology = from_json("degree", "[[0, 1], [1, 0], [2, 0]]", 4).unwrap();
+        assert_eq!(topology.degree(0), 2);
+        assert_eq!(topology.degree(1), 1);
+        assert_eq!(topology.degree(3), 0);
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::Context;
+use concordium_rust_sdk::common::types::{AccountAddress, Amount, TransactionTime};
+use concordium_rust_sdk::id::types::AccountKeys;
+use concordium_rust_sdk::types::transactions::{
+    send, AccountTransaction, BlockItem, EncodedPayload,
+};
+use concordium_rust_sdk::types::Nonce;
+use node_client::{Client, NodeEndpoint, DEFAULT_TOKEN};
+use serde::Deserialize;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use tonic::transport::Endpoint;
+
+/// The amount of a test transfer in microCCD.
+const TRANSFER_AMOUNT: u64 = 1_000_000;
+
+/// How long a test transfer can wait to be included in a block.
+const TRANSFER_EXPIRY: Duration = Duration::from_secs(3600);
+
+/// A genesis account as written by the genesis tool.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenesisAccount {
+    address: AccountAddress,
+    account_keys: AccountKeys,
+}
+
+/// Read the accounts in `genesis_root/accounts`, sorted by file name.
+fn genesis_accounts(genesis_root: &Path) -> anyhow::Result<Vec<GenesisAccount>> {
+    let dir = genesis_root.join("accounts");
+    let mut paths = std::fs::read_dir(&dir)
+        .context(format!(
+            "cannot read the genesis accounts in {}",
+            dir.display()
+        ))?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "json"));
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| {
+            let contents = std::fs::read_to_string(path)
+                .context(format!("cannot read account file {}", path.display()))?;
+            serde_json::from_str(&contents)
+                .context(format!("invalid account file {}", path.display()))
+        })
+        .collect()
+}
+
+/// A transfer of `TRANSFER_AMOUNT` from the genesis account `from` to the
+/// account `to`, signed with every key of `from`. The SDK charges its energy.
+fn transfer(
+    from: &GenesisAccount,
+    to: AccountAddress,
+    nonce: Nonce,
+    expiry: TransactionTime,
+) -> AccountTransaction<EncodedPayload> {
+    send::transfer(
+        &from.account_keys,
+        from.address,
+        nonce,
+        expiry,
+        to,
+        Amount::from_micro_ccd(TRANSFER_AMOUNT),
+    )
+}
+
+/// Send a transfer from the first genesis account to the second one through
+/// the node listening for gRPC on `rpc_port`, and return the hash of the
+/// transaction.
+pub(crate) async fn send_test_transfer(
+    genesis_root: &Path,
+    rpc_port: u16,
+) -> anyhow::Result<String> {
+    let accounts = genesis_accounts(genesis_root)?;
+    let (from, to) = match accounts.as_slice() {
+        [from, to, ..] => (from, to),
+        _ => anyhow::bail!(
+            "a transfer needs two genesis accounts in {}",
+            genesis_root.join("accounts").display()
+        ),
+    };
+    let endpoint = Endpoint::from_shared(format!("http://127.0.0.1:{}", rpc_port))?;
+    let mut client = Client::connect(NodeEndpoint::new(endpoint, DEFAULT_TOKEN, None)?)
+        .await
+        .context("cannot connect to the node")?;
+    let nonce = client
+        .sdk()
+        .get_next_account_nonce(&from.address)
+        .await?
+        .nonce;
+
+    let expiry = (SystemTime::now() + TRANSFER_EXPIRY)
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs();
+    let block_item = BlockItem::from(transfer(
+        from,
+        to.address,
+        nonce,
+        TransactionTime::from_seconds(expiry),
+    ));
+    let hash = client.sdk().send_block_item(&block_item).await?;
+    Ok(hash.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use concordium_rust_sdk::common;
+
+    /// The key of test 1 of RFC 8032.
+    const SIGN_KEY: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+    const VERIFY_KEY: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+
+    fn test_transfer() -> AccountTransaction<EncodedPayload> {
+        let account: GenesisAccount = serde_json::from_value(serde_json::json!({
+            "address": AccountAddress([0x11; 32]),
+            "accountKeys": {
+                "keys": {"0": {
+                    "keys": {"0": {"signKey": SIGN_KEY, "verifyKey": VERIFY_KEY}},
+                    "threshold": 1
+                }},
+                "threshold": 1
+            }
+        }))
+        .unwrap();
+        transfer(
+            &account,
+            AccountAddress([0x22; 32]),
+            Nonce { nonce: 7 },
+            TransactionTime::from_seconds(1_700_000_000),
+        )
+    }
+
+    #[test]
+    fn energy_of_a_transfer() {
+        // a single signature and the 41 bytes of the tag, receiver and amount.
+        assert_eq!(test_transfer().header.energy_amount.energy, 501);
+    }
+
+    #[test]
+    fn transfer_bytes() {
+        let block_item = BlockItem::from(test_transfer());
+        let bytes = common::to_bytes(&block_item);
+        let signature_start = 1 + 1 + 2 + 1 + 2;
+        let (head, rest) = bytes.split_at(signature_start);
+        let (_signature, body) = rest.split_at(64);
+        // the account transaction tag, one credential with index 0 that has one
+        // key with index 0, and the length of its signature.
+        assert_eq!(hex::encode(head), "00010001000040");
+        let header = [
+            "11".repeat(32),                // the sender
+            "0000000000000007".to_string(), // the nonce
+            "00000000000001f5".to_string(), // 501 energy
+            "00000029".to_string(),         // 41 bytes of payload
+            "000000006553f100".to_string(), // the expiry
+        ]
+        .concat();
+        let payload = [
+            "03".to_string(),               // a simple transfer
+            "22".repeat(32),                // the receiver
+            "00000000000f4240".to_string(), // 1 CCD
+        ]
+        .concat();
+        assert_eq!(hex::encode(body), header + &payload);
+        // ed25519 signatures are deterministic, so the hash covers the
+        // signature as well.
+        assert_eq!(
+            block_item.hash().to_string(),
+            "68d4287340f884b1415dde8c8271ae2af6567da67ce650c9692e28ef95114c0c"
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The columns of a block-analyzer csv file that are compared.
+#[derive(Deserialize)]
+struct GrpcRow {
+    #[serde(rename = "Node id")]
+    node: String,
+    #[serde(rename = "Block height")]
+    block_height: u64,
+    #[serde(rename = "Block hash")]
+    block_hash: String,
+    #[serde(rename = "Block execution time (millis)")]
+    execution_time: i64,
+}
+
+#[derive(Serialize)]
+pub(crate) struct CorrelationRow {
+    #[serde(rename = "Source")]
+    source: String,
+    #[serde(rename = "Node id")]
+    node: String,
+    #[serde(rename = "Block height")]
+    block_height: u64,
+    #[serde(rename = "Block hash")]
+    block_hash: String,
+    #[serde(rename = "Log execution time (millis)")]
+    log_execution_time: i64,
+    #[serde(rename = "gRPC execution time (millis)")]
+    grpc_execution_time: i64,
+    /// The log time minus the gRPC time.
+    #[serde(rename = "Delta (millis)")]
+    delta: i64,
+}
+
+/// The block execution times measured by block-analyzer, to compare the ones
+/// measured in the logs with.
+pub(crate) struct Correlation {
+    rows: Vec<GrpcRow>,
+    /// The indices of the rows of every block by hash.
+    by_hash: HashMap<String, Vec<usize>>,
+    /// The indices of the rows of every block by height.
+    by_height: HashMap<u64, Vec<usize>>,
+}
+
+impl Correlation {
+    /// Read the rows of the csv file written by block-analyzer at `path`.
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Correlation> {
+        let mut reader = csv::Reader::from_path(path).context(format!(
+            "cannot read block-analyzer rows {}",
+            path.display()
+        ))?;
+        let rows = reader
+            .deserialize()
+            .collect::<Result<Vec<GrpcRow>, _>>()
+            .context(format!("invalid block-analyzer rows in {}", path.display()))?;
+        Ok(Correlation::new(rows))
+    }
+
+    fn new(rows: Vec<GrpcRow>) -> Correlation {
+        let mut by_hash: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut by_height: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (i, row) in rows.iter().enumerate() {
+            by_hash.entry(row.block_hash.clone()).or_default().push(i);
+            by_height.entry(row.block_height).or_default().push(i);
+        }
+        Correlation {
+            rows,
+            by_hash,
+            by_height,
+        }
+    }
+
+    /// Compare the `execution_time` of a block measured in the log `source`
+    /// with every node that block-analyzer measured it on. The block is found
+    /// by its hash `key` if the log has it, otherwise by the chain `height`
+    /// logged with it. A block with neither is not compared.
+    pub(crate) fn compare(
+        &self,
+        source: &str,
+        key: &str,
+        height: Option<u64>,
+        execution_time: i64,
+    ) -> Vec<CorrelationRow> {
+        let matches = match height {
+            _ if !key.is_empty() => self.by_hash.get(key),
+            Some(height) => self.by_height.get(&height),
+            None => None,
+        };
+        matches
+            .into_iter()
+            .flatten()
+            .map(|&i| {
+                let row = &self.rows[i];
+                CorrelationRow {
+                    source: source.to_string(),
+                    node: row.node.clone(),
+                    block_height: row.block_height,
+                    block_hash: row.block_hash.clone(),
+                    log_execution_time: execution_time,
+                    grpc_execution_time: row.execution_time,
+                    delta: execution_time - row.execution_time,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn correlation() -> Correlation {
+        let row = |node: &str, block_height, block_hash: &str, execution_time| GrpcRow {
+            node: node.to_string(),
+            block_height,
+            block_hash: block_hash.to_string(),
+            execution_time,
+        };
+        Correlation::new(vec![
+            row("node-0", 1, "aa", 10),
+            row("node-1", 1, "aa", 12),
+            row("node-0", 2, "bb", 20),
+        ])
+    }
+
+    #[test]
+    fn joins_on_hash() {
+        let rows = correlation().compare("peer-0.log", "bb", Some(1), 25);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].block_height, 2);
+        assert_eq!(rows[0].grpc_execution_time, 20);
+        assert_eq!(rows[0].delta, 5);
+        assert!(correlation()
+            .compare("peer-0.log", "cc", Some(1), 25)
+            .is_empty());
+    }
+
+    #[test]
+    fn joins_on_height_without_hash() {
+        let rows = correlation().compare("peer-0.log", "", Some(1), 11);
+        let deltas: Vec<(&str, i64)> = rows
+            .iter()
+            .map(|row| (row.node.as_str(), row.delta))
+            .collect();
+        assert_eq!(deltas, [("node-0", 1), ("node-1", -1)]);
+        assert!(rows.iter().all(|row| row.block_hash == "aa"));
+    }
+
+    #[test]
+    fn does_not_join_without_hash_or_height() {
+        assert!(correlation().compare("peer-0.log", "", None, 11).is_empty());
+    }
+}
@@ -0,0 +1,1088 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use clap::arg_enum;
+use correlate::Correlation;
+use output_sink::{Format, OutputSink};
+use rayon::prelude::*;
+use reader::LogReader;
+use rules::Rule;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+use structopt::StructOpt;
+
+mod correlate;
+mod reader;
+mod rules;
+mod summary;
+
+arg_enum! {
+    #[derive(Debug)]
+    enum Metric {
+        // Get the block execution by subtracting the block receive time from
+        // block arrive time. Note. the log must've been obtained via debug
+        BlockExecution,
+        // Get the startup time of the node.
+        StartupTime,
+        // Get the time between the arrival of a block and its finalization.
+        FinalizationTime,
+        // Count the catch-up events of the node.
+        CatchupCount,
+        // Get the duration of every catch-up of the node.
+        Catchup,
+        // Get the peer connect and disconnect events over time.
+        PeerChurn,
+        // Get the number of pending transactions over time.
+        MempoolSize,
+        // Get the restarts of the node and warn about gaps in the log.
+        Restarts,
+    }
+}
+
+/// The line the node logs every time it starts.
+const STARTUP_BANNER: &str = "Starting up the consensus layer";
+
+/// Lines of the node log that mark a catch-up event.
+const CATCHUP_PATTERNS: &[&str] = &["Catch-up required", "Catching up"];
+/// Lines of the node log that mark the end of a catch-up.
+const CATCHUP_END_PATTERNS: &[&str] = &["Catch-up complete", "Catch-up finished"];
+
+/// Lines of the node log that mark a peer connecting or disconnecting.
+const CONNECT_PATTERNS: &[&str] = &["Connected to peer", "New peer"];
+const DISCONNECT_PATTERNS: &[&str] = &["Removing peer", "Dropping connection"];
+
+/// Lines of the node log that report the number of pending transactions,
+/// which is the first number after the pattern.
+const MEMPOOL_PATTERNS: &[&str] = &["Pending transactions", "pending transactions"];
+
+#[derive(Serialize)]
+struct Row {
+    #[serde(rename = "Source")]
+    source: String,
+    /// Counts the measurements of a rule, which for BlockExecution are the
+    /// blocks.
+    #[serde(rename = "Block height")]
+    block_height: usize,
+    #[serde(rename = "Execution time")]
+    execution_time: i64,
+    /// The key of the measurement, e.g. the hash of the block.
+    #[serde(skip)]
+    key: String,
+    /// The chain height of the block, if the end line logs it.
+    #[serde(skip)]
+    height: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct FinalizationRow {
+    #[serde(rename = "Source")]
+    source: String,
+    #[serde(rename = "Block hash")]
+    block_hash: String,
+    #[serde(rename = "Block height")]
+    block_height: Option<u64>,
+    #[serde(rename = "Finalization time")]
+    finalization_time: i64,
+}
+
+#[derive(Serialize)]
+struct CatchupRow {
+    #[serde(rename = "Source")]
+    source: String,
+    #[serde(rename = "Time")]
+    time: DateTime<Utc>,
+    #[serde(rename = "Catch-up count")]
+    count: usize,
+}
+
+#[derive(Serialize)]
+struct CatchupDurationRow {
+    #[serde(rename = "Source")]
+    source: String,
+    #[serde(rename = "Start")]
+    start: DateTime<Utc>,
+    /// Empty if the catch-up did not finish before the restart or the end of
+    /// the log.
+    #[serde(rename = "End")]
+    end: Option<DateTime<Utc>>,
+    #[serde(rename = "Duration (millis)")]
+    duration: Option<i64>,
+    /// The blocks that arrived during the catch-up. Empty if the log has no
+    /// block arrivals, which are only logged at debug level.
+    #[serde(rename = "Blocks caught up")]
+    blocks: Option<u64>,
+}
+
+/// A catch-up that has started but not finished yet.
+#[derive(Clone, Copy)]
+struct OpenCatchup {
+    start: DateTime<Utc>,
+    blocks: u64,
+}
+
+impl OpenCatchup {
+    /// The row of the catch-up, which is still open without an `end`. The
+    /// blocks are only counted if the log has `arrivals`.
+    fn row(self, source: &str, end: Option<DateTime<Utc>>, arrivals: bool) -> CatchupDurationRow {
+        CatchupDurationRow {
+            source: source.to_string(),
+            start: self.start,
+            end,
+            duration: end.map(|end| (end - self.start).num_milliseconds()),
+            blocks: arrivals.then_some(self.blocks),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct PeerRow {
+    #[serde(rename = "Source")]
+    source: String,
+    #[serde(rename = "Time")]
+    time: DateTime<Utc>,
+    #[serde(rename = "Event")]
+    event: &'static str,
+    /// The connects minus the disconnects since the start of the node. It
+    /// is off by the peers that were connected when the log started.
+    #[serde(rename = "Peers")]
+    peers: i64,
+}
+
+#[derive(Serialize)]
+struct MempoolRow {
+    #[serde(rename = "Source")]
+    source: String,
+    #[serde(rename = "Time")]
+    time: DateTime<Utc>,
+    #[serde(rename = "Pending transactions")]
+    pending: u64,
+}
+
+#[derive(Serialize)]
+struct RestartRow {
+    #[serde(rename = "Source")]
+    source: String,
+    #[serde(rename = "Time")]
+    time: DateTime<Utc>,
+    #[serde(rename = "Restart")]
+    restart: usize,
+}
+
+/// A row of one of the metrics.
+enum MetricRow {
+    /// A row of the rule with the index.
+    Rule(usize, Row),
+    Finalization(FinalizationRow),
+    Catchup(CatchupRow),
+    CatchupDuration(CatchupDurationRow),
+    Peer(PeerRow),
+    Mempool(MempoolRow),
+    Restart(RestartRow),
+}
+
+#[derive(StructOpt)]
+pub struct Config {
+    #[structopt(
+        long = "in",
+        help = "Log files to inspect, e.g. peer-0.log peer-1.log. Every row tells which file it came from.",
+        required = true
+    )]
+    log_files: Vec<PathBuf>,
+    #[structopt(
+        long = "cfg",
+        help = "Metrics to inspect: BlockExecution, StartupTime, FinalizationTime, CatchupCount, Catchup, PeerChurn, MempoolSize or Restarts"
+    )]
+    metrics: Vec<Metric>,
+    #[structopt(
+        long = "out",
+        help = "File to output the rows to. The metrics other than BlockExecution are written next to it, e.g. foo-finalization.csv. If omitted they are written to stdout."
+    )]
+    out: Option<PathBuf>,
+    #[structopt(
+        long = "format",
+        help = "The format of the rows.",
+        possible_values = &Format::variants(),
+        case_insensitive = true,
+        default_value = "csv"
+    )]
+    format: Format,
+    #[structopt(
+        long = "timestamp-length",
+        help = "Length of the timestamp at the start of every line. By default it ends at the first whitespace."
+    )]
+    timestamp_length: Option<usize>,
+    #[structopt(
+        long = "follow",
+        help = "Keep reading the lines appended to the log file, like tail -f, and write the rows as they complete."
+    )]
+    follow: bool,
+    #[structopt(
+        long = "poll-interval",
+        help = "Seconds between checking for new lines with --follow.",
+        default_value = "1"
+    )]
+    poll_interval: u64,
+    #[structopt(
+        long = "patterns",
+        help = "TOML file with rules measuring the time from a line matching a start regex to a line matching an end regex. A rule named BlockExecution replaces the built-in patterns."
+    )]
+    patterns: Option<PathBuf>,
+    #[structopt(
+        long = "max-gap-secs",
+        help = "With the Restarts metric, warn when consecutive lines are more than this many seconds apart.",
+        default_value = "60"
+    )]
+    max_gap_secs: i64,
+    #[structopt(
+        long = "threads",
+        help = "The number of log files analyzed at once. Defaults to the number of cores."
+    )]
+    threads: Option<usize>,
+    #[structopt(
+        long = "summary",
+        help = "Print the count, min, max, mean, median and 90th, 95th and 99th percentile of the times measured by BlockExecution and the rules of every file to stderr.",
+        conflicts_with = "follow"
+    )]
+    summary: bool,
+    #[structopt(
+        long = "histogram",
+        help = "Also print a histogram of the times with --summary.",
+        requires = "summary"
+    )]
+    histogram: bool,
+    #[structopt(
+        long = "correlate",
+        help = "Csv file written by block-analyzer to compare the BlockExecution times with. The blocks are joined on their hash if the log has it, otherwise on the chain height logged with it, and the differences are written next to --out, e.g. foo-correlation.csv."
+    )]
+    correlate: Option<PathBuf>,
+    #[structopt(
+        long = "since",
+        help = "Skip the lines before this RFC 3339 time, e.g. 2022-05-22T10:45:00Z, and the lines without a timestamp."
+    )]
+    since: Option<DateTime<Utc>>,
+    #[structopt(
+        long = "until",
+        help = "Stop reading at the first line after this RFC 3339 time, and skip the lines without a timestamp."
+    )]
+    until: Option<DateTime<Utc>>,
+    #[structopt(
+        long = "first-n-blocks",
+        help = "Stop reading after the first N blocks measured by BlockExecution, e.g. to check the startup of a node in a large log."
+    )]
+    first_n_blocks: Option<usize>,
+}
+
+/// The metrics selected with `--cfg`.
+struct Metrics {
+    /// The metrics measured by start and end patterns, including
+    /// BlockExecution.
+    rules: Vec<Rule>,
+    startup_time: bool,
+    finalization_time: bool,
+    catchup_count: bool,
+    catchup_duration: bool,
+    peer_churn: bool,
+    mempool_size: bool,
+    /// The gap between lines to warn about, if restarts are reported.
+    restarts: Option<chrono::Duration>,
+    /// The index of the BlockExecution rule and the number of its blocks after
+    /// which the log is no longer read, with `--first-n-blocks`.
+    first_blocks: Option<(usize, usize)>,
+}
+
+/// The times of the lines that are analyzed, given with `--since` and
+/// `--until`.
+#[derive(Clone, Copy)]
+struct TimeRange {
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+}
+
+/// Where the rows of the selected metrics are written.
+struct Outputs {
+    /// The output of every rule.
+    rules: Vec<OutputSink>,
+    finalization: Option<OutputSink>,
+    catchup: Option<OutputSink>,
+    catchup_duration: Option<OutputSink>,
+    peers: Option<OutputSink>,
+    mempool: Option<OutputSink>,
+    restarts: Option<OutputSink>,
+    correlation: Option<Correlated>,
+}
+
+/// The comparison of the BlockExecution times with the ones of block-analyzer.
+struct Correlated {
+    /// The index of the BlockExecution rule.
+    rule: usize,
+    correlation: Correlation,
+    output: OutputSink,
+    /// The blocks of the log without a hash or height, which are not compared.
+    unjoined: usize,
+}
+
+impl Outputs {
+    /// Write `row` to the output of its metric.
+    fn write(&mut self, row: MetricRow) -> anyhow::Result<()> {
+        match row {
+            MetricRow::Rule(i, row) => {
+                if let Some(correlated) = self.correlation.as_mut().filter(|c| c.rule == i) {
+                    if row.key.is_empty() && row.height.is_none() {
+                        correlated.unjoined += 1;
+                    }
+                    let compared = correlated.correlation.compare(
+                        &row.source,
+                        &row.key,
+                        row.height,
+                        row.execution_time,
+                    );
+                    for compared in compared {
+                        correlated.output.write(compared)?;
+                    }
+                }
+                self.rules[i].write(row)
+            }
+            MetricRow::Finalization(row) => write_row(&mut self.finalization, row),
+            MetricRow::Catchup(row) => write_row(&mut self.catchup, row),
+            MetricRow::CatchupDuration(row) => write_row(&mut self.catchup_duration, row),
+            MetricRow::Peer(row) => write_row(&mut self.peers, row),
+            MetricRow::Mempool(row) => write_row(&mut self.mempool, row),
+            MetricRow::Restart(row) => write_row(&mut self.restarts, row),
+        }
+    }
+}
+
+impl Config {
+    /// Write the rows to `out` and in `format` instead, if they are given.
+    pub fn with_output(
+        mut self,
+        out: Option<PathBuf>,
+        format: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        if let Some(out) = out {
+            self.out = Some(out);
+        }
+        if let Some(format) = format {
+            self.format = format.parse().map_err(anyhow::Error::msg)?;
+        }
+        Ok(self)
+    }
+}
+
+/// Extract the selected metrics from node log files.
+pub fn run(cfg: Config) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        !cfg.follow || cfg.log_files.len() == 1,
+        "--follow can only be used with a single log file."
+    );
+    anyhow::ensure!(cfg.threads != Some(0), "--threads must be at least 1");
+    if let (Some(since), Some(until)) = (cfg.since, cfg.until) {
+        anyhow::ensure!(since <= until, "--since must not be after --until");
+    }
+    let range = TimeRange {
+        since: cfg.since,
+        until: cfg.until,
+    };
+    anyhow::ensure!(
+        cfg.first_n_blocks != Some(0),
+        "--first-n-blocks must be at least 1"
+    );
+    let follow = cfg.follow.then(|| Duration::from_secs(cfg.poll_interval));
+
+    let selected = |metric: fn(&Metric) -> bool| cfg.metrics.iter().any(metric);
+    let rules = rules::load(
+        cfg.patterns.as_deref(),
+        selected(|m| matches!(m, Metric::BlockExecution)),
+    )?;
+    let first_blocks = match cfg.first_n_blocks {
+        Some(n) => Some((
+            rules
+                .iter()
+                .position(|rule| rule.name == rules::BLOCK_EXECUTION)
+                .context("--first-n-blocks counts the BlockExecution measurements, select them with --cfg BlockExecution")?,
+            n,
+        )),
+        None => None,
+    };
+    let metrics = Metrics {
+        rules,
+        startup_time: selected(|m| matches!(m, Metric::StartupTime)),
+        finalization_time: selected(|m| matches!(m, Metric::FinalizationTime)),
+        catchup_count: selected(|m| matches!(m, Metric::CatchupCount)),
+        catchup_duration: selected(|m| matches!(m, Metric::Catchup)),
+        peer_churn: selected(|m| matches!(m, Metric::PeerChurn)),
+        mempool_size: selected(|m| matches!(m, Metric::MempoolSize)),
+        restarts: selected(|m| matches!(m, Metric::Restarts))
+            .then(|| chrono::Duration::seconds(cfg.max_gap_secs)),
+        first_blocks,
+    };
+
+    let out = cfg.out.as_deref();
+    let open = |suffix: &str| open_output(out, suffix, cfg.format, cfg.follow);
+    let open_selected = |suffix: &str, selected: bool| selected.then(|| open(suffix)).transpose();
+    let mut outputs = Outputs {
+        rules: metrics
+            .rules
+            .iter()
+            .map(|rule| match rule.name.as_str() {
+                rules::BLOCK_EXECUTION => open(""),
+                name => open(&format!("-{}", name)),
+            })
+            .collect::<anyhow::Result<_>>()?,
+        finalization: open_selected("-finalization", metrics.finalization_time)?,
+        catchup: open_selected("-catchup", metrics.catchup_count)?,
+        catchup_duration: open_selected("-catchup-duration", metrics.catchup_duration)?,
+        peers: open_selected("-peers", metrics.peer_churn)?,
+        mempool: open_selected("-mempool", metrics.mempool_size)?,
+        restarts: open_selected("-restarts", metrics.restarts.is_some())?,
+        correlation: match &cfg.correlate {
+            Some(path) => Some(Correlated {
+                rule: metrics
+                    .rules
+                    .iter()
+                    .position(|rule| rule.name == rules::BLOCK_EXECUTION)
+                    .context("--correlate compares the BlockExecution times, select them with --cfg BlockExecution")?,
+                correlation: Correlation::load(path)?,
+                output: open("-correlation")?,
+                unjoined: 0,
+            }),
+            None => None,
+        },
+    };
+
+    // the log is read a line at a time, since node logs can be larger than
+    // the available memory.
+    let open_log = |log_file: &PathBuf| {
+        let source = log_file.display().to_string();
+        LogReader::open(log_file.clone(), follow)
+            .context(format!("cannot analyze {}", source))
+            .map(|lines| (lines, source))
+    };
+    if cfg.follow {
+        // the rows are written as the lines are appended.
+        let (lines, source) = open_log(&cfg.log_files[0])?;
+        analyze_log(
+            lines,
+            &source,
+            &metrics,
+            cfg.timestamp_length,
+            range,
+            &mut |row| outputs.write(row),
+        )?;
+    } else {
+        // every file is analyzed on its own thread, so the state of one does
+        // not leak into the next. The rows are written in the order of the
+        // files once all are analyzed.
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(cfg.threads.unwrap_or_default())
+            .build()?;
+        let rows = pool.install(|| {
+            cfg.log_files
+                .par_iter()
+                .map(|log_file| {
+                    let (lines, source) = open_log(log_file)?;
+                    let mut rows = vec![];
+                    analyze_log(
+                        lines,
+                        &source,
+                        &metrics,
+                        cfg.timestamp_length,
+                        range,
+                        &mut |row| {
+                            rows.push(row);
+                            Ok(())
+                        },
+                    )?;
+                    Ok(rows)
+                })
+                .collect::<anyhow::Result<Vec<_>>>()
+        })?;
+        // the measured times of every rule and file, for the summary.
+        let mut times: BTreeMap<(usize, String), Vec<i64>> = BTreeMap::new();
+        for row in rows.into_iter().flatten() {
+            match &row {
+                MetricRow::Rule(i, row) if cfg.summary => times
+                    .entry((*i, row.source.clone()))
+                    .or_default()
+                    .push(row.execution_time),
+                _ => {}
+            }
+            outputs.write(row)?;
+        }
+        if cfg.summary {
+            // the rows may be written to stdout, so the summary goes to stderr.
+            summary::write_summary(&metrics.rules, times, cfg.histogram, &mut std::io::stderr())?;
+        }
+    }
+
+    let Outputs {
+        rules,
+        finalization,
+        catchup,
+        catchup_duration,
+        peers,
+        mempool,
+        restarts,
+        correlation,
+    } = outputs;
+    for output in rules
+        .into_iter()
+        .chain(finalization)
+        .chain(catchup)
+        .chain(catchup_duration)
+        .chain(peers)
+        .chain(mempool)
+        .chain(restarts)
+        .chain(correlation.map(|correlated| {
+            if correlated.unjoined > 0 {
+                eprintln!(
+                    "{} blocks were not correlated, since their lines have neither a block hash nor a height",
+                    correlated.unjoined
+                );
+            }
+            correlated.output
+        }))
+    {
+        output.finish()?;
+    }
+    Ok(())
+}
+
+/// Extract the selected `metrics` from the `lines` of the log file `source`
+/// and pass their rows to `emit`. Only the lines in the time `range` are
+/// analyzed.
+fn analyze_log(
+    lines: LogReader,
+    source: &str,
+    metrics: &Metrics,
+    timestamp_length: Option<usize>,
+    range: TimeRange,
+    emit: &mut impl FnMut(MetricRow) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut startup_time = metrics.startup_time;
+    // the start times of the measurements of every rule that have not ended
+    // yet by their key, and the number of measurements of every rule.
+    let mut started: Vec<rules::Started> = metrics
+        .rules
+        .iter()
+        .map(|_| rules::Started::default())
+        .collect();
+    let mut counts = vec![0; metrics.rules.len()];
+    let mut startup_time_start = None;
+    // the arrival times of the blocks that are not finalized yet.
+    let mut arrivals: HashMap<String, DateTime<Utc>> = HashMap::new();
+    let mut catchups = 0;
+    // whether the latest catch-up counted by CatchupCount has not finished.
+    let mut counted_catchup = false;
+    let mut catchup: Option<OpenCatchup> = None;
+    // whether the log has block arrivals to count during a catch-up.
+    let mut saw_arrivals = false;
+    let mut peers = 0;
+    let mut connects = 0;
+    let mut disconnects = 0;
+    let mut starts = 0;
+    let mut last_time = None;
+    let mut past_until = false;
+    let mut found_first_blocks = false;
+
+    // a matching line without a timestamp is reported and skipped for all
+    // metrics.
+    let timestamp = |line: &str| match extract_timestamp(line, timestamp_length) {
+        Ok(timestamp) => Some(timestamp),
+        Err(e) => {
+            eprintln!("{}: Skipping line {:?}: {:#}", source, line, e);
+            None
+        }
+    };
+
+    'lines: for line in lines {
+        let line = line.context(format!("cannot read {}", source))?;
+        let line = line.as_str();
+        if metrics.rules.is_empty()
+            && !startup_time
+            && !metrics.finalization_time
+            && !metrics.catchup_count
+            && !metrics.catchup_duration
+            && !metrics.peer_churn
+            && !metrics.mempool_size
+            && metrics.restarts.is_none()
+        {
+            break;
+        }
+
+        // the lines outside the range are skipped before they are matched, so
+        // a measurement is only paired within the range.
+        if range.since.is_some() || range.until.is_some() {
+            let Ok(time) = extract_timestamp(line, timestamp_length) else {
+                continue;
+            };
+            // the log is in order, so the rest is after the range too.
+            if range.until.is_some_and(|until| time > until) {
+                past_until = true;
+                break;
+            }
+            if range.since.is_some_and(|since| time < since) {
+                continue;
+            }
+        }
+
+        if let Some(max_gap) = metrics.restarts {
+            // lines without a timestamp, e.g. continued lines, are not warned about.
+            if let Ok(time) = extract_timestamp(line, timestamp_length) {
+                if let Some(last_time) = last_time.filter(|&last| time - last > max_gap) {
+                    eprintln!(
+                        "{}: Warning: no lines for {} between {} and {}",
+                        source,
+                        time - last_time,
+                        last_time,
+                        time
+                    );
+                }
+                last_time = Some(time);
+            }
+        }
+
+        if line.contains(STARTUP_BANNER) {
+            starts += 1;
+            // the measurements that started before a restart never end.
+            if starts > 1 {
+                report_unended(source, &metrics.rules, &mut started, "before the restart ");
+                arrivals.clear();
+                // a restarted node has no peers, nor a catch-up.
+                peers = 0;
+                counted_catchup = false;
+                if let Some(open) = catchup.take() {
+                    eprintln!(
+                        "{}: Catch-up started at {} did not finish before the restart",
+                        source, open.start
+                    );
+                    emit(MetricRow::CatchupDuration(open.row(
+                        source,
+                        None,
+                        saw_arrivals,
+                    )))?;
+                }
+                if metrics.restarts.is_some() {
+                    let Some(time) = timestamp(line) else {
+                        continue;
+                    };
+                    eprintln!("{}: Restart {} at {}", source, starts - 1, time);
+                    let row = RestartRow {
+                        source: source.to_string(),
+                        time,
+                        restart: starts - 1,
+                    };
+                    emit(MetricRow::Restart(row))?;
+                }
+            }
+        }
+
+        if startup_time {
+            if line.contains(STARTUP_BANNER) {
+                let Some(start) = timestamp(line) else {
+                    continue;
+                };
+                startup_time_start = Some(start);
+            } else if line.contains("Consensus layer started") {
+                let Some(startup_time_end) = timestamp(line) else {
+                    continue;
+                };
+                if let Some(startup_time_start) = startup_time_start {
+                    eprintln!(
+                        "{}: Consensus started up in {}",
+                        source,
+                        startup_time_end - startup_time_start
+                    );
+                    startup_time = false;
+                }
+            }
+        }
+
+        for (i, rule) in metrics.rules.iter().enumerate() {
+            if let Some(key) = rule.start_key(line) {
+                // a repeated start, e.g. a block received twice, is ignored.
+                if !started[i].contains(key) {
+                    let Some(start_time) = timestamp(line) else {
+                        continue 'lines;
+                    };
+                    eprintln!(
+                        "{}: {} {} Started {}",
+                        source,
+                        rule.name,
+                        measurement(key, counts[i]),
+                        start_time
+                    );
+                    started[i].insert(key, start_time);
+                }
+            } else if let Some(key) = rule.end_key(line) {
+                if started[i].ends(key) {
+                    let Some(end_time) = timestamp(line) else {
+                        continue 'lines;
+                    };
+                    let Some(start_time) = started[i].end(key) else {
+                        continue;
+                    };
+                    eprintln!(
+                        "{}: {} {} Ended {}",
+                        source,
+                        rule.name,
+                        measurement(key, counts[i]),
+                        end_time
+                    );
+                    let row = Row {
+                        source: source.to_string(),
+                        block_height: counts[i],
+                        execution_time: (end_time - start_time).num_milliseconds(),
+                        key: key.to_string(),
+                        height: word_after(line, "at height ").and_then(|h| h.parse().ok()),
+                    };
+                    emit(MetricRow::Rule(i, row))?;
+                    counts[i] += 1;
+                }
+            }
+        }
+        // the rest of the log is not read once the first blocks are measured.
+        if let Some((rule, n)) = metrics.first_blocks {
+            if counts[rule] >= n {
+                found_first_blocks = true;
+                break;
+            }
+        }
+
+        if metrics.finalization_time {
+            if line.contains(" arrived") {
+                if let Some(hash) = word_after(line, "Block ") {
+                    let Some(arrive_time) = timestamp(line) else {
+                        continue;
+                    };
+                    arrivals.insert(hash.to_string(), arrive_time);
+                }
+            } else if line.contains("is finalized") {
+                let arrival =
+                    word_after(line, "Block ").and_then(|hash| arrivals.remove_entry(hash));
+                if let Some((block_hash, arrive_time)) = arrival {
+                    let Some(finalized_time) = timestamp(line) else {
+                        continue;
+                    };
+                    let finalization_time = finalized_time - arrive_time;
+                    eprintln!(
+                        "{}: Block {} Finalized after {}",
+                        source, block_hash, finalization_time
+                    );
+                    let row = FinalizationRow {
+                        source: source.to_string(),
+                        block_hash,
+                        block_height: word_after(line, "at height ").and_then(|h| h.parse().ok()),
+                        finalization_time: finalization_time.num_milliseconds(),
+                    };
+                    emit(MetricRow::Finalization(row))?;
+                }
+            }
+        }
+
+        if metrics.catchup_count {
+            // the node may log several lines while it catches up, which are
+            // counted once like by the Catchup metric.
+            if CATCHUP_PATTERNS.iter().any(|p| line.contains(p)) {
+                if !counted_catchup {
+                    let Some(time) = timestamp(line) else {
+                        continue;
+                    };
+                    counted_catchup = true;
+                    catchups += 1;
+                    eprintln!("{}: Catch-up {} at {}", source, catchups, time);
+                    let row = CatchupRow {
+                        source: source.to_string(),
+                        time,
+                        count: catchups,
+                    };
+                    emit(MetricRow::Catchup(row))?;
+                }
+            } else if CATCHUP_END_PATTERNS.iter().any(|p| line.contains(p)) {
+                counted_catchup = false;
+            }
+        }
+
+        if metrics.catchup_duration {
+            if line.contains(" arrived") && word_after(line, "Block ").is_some() {
+                saw_arrivals = true;
+                if let Some(open) = &mut catchup {
+                    open.blocks += 1;
+                }
+            }
+            if CATCHUP_PATTERNS.iter().any(|p| line.contains(p)) {
+                // the node may log several lines while it catches up.
+                if catchup.is_none() {
+                    let Some(start) = timestamp(line) else {
+                        continue;
+                    };
+                    eprintln!("{}: Catch-up started at {}", source, start);
+                    catchup = Some(OpenCatchup { start, blocks: 0 });
+                }
+            } else if CATCHUP_END_PATTERNS.iter().any(|p| line.contains(p)) {
+                if let Some(open) = catchup {
+                    let Some(end) = timestamp(line) else {
+                        continue;
+                    };
+                    catchup = None;
+                    eprintln!(
+                        "{}: Catch-up finished at {} after {}",
+                        source,
+                        end,
+                        end - open.start
+                    );
+                    emit(MetricRow::CatchupDuration(open.row(
+                        source,
+                        Some(end),
+                        saw_arrivals,
+                    )))?;
+                }
+            }
+        }
+
+        if metrics.peer_churn {
+            let event = if CONNECT_PATTERNS.iter().any(|p| line.contains(p)) {
+                Some(("connect", 1))
+            } else if DISCONNECT_PATTERNS.iter().any(|p| line.contains(p)) {
+                Some(("disconnect", -1))
+            } else {
+                None
+            };
+            if let Some((event, change)) = event {
+                let Some(time) = timestamp(line) else {
+                    continue;
+                };
+                peers += change;
+                if change > 0 {
+                    connects += 1;
+                } else {
+                    disconnects += 1;
+                }
+                eprintln!("{}: Peer {} at {}, {} peers", source, event, time, peers);
+                let row = PeerRow {
+                    source: source.to_string(),
+                    time,
+                    event,
+                    peers,
+                };
+                emit(MetricRow::Peer(row))?;
+            }
+        }
+
+        if metrics.mempool_size {
+            if let Some(pending) = pending_count(line) {
+                let Some(time) = timestamp(line) else {
+                    continue;
+                };
+                eprintln!("{}: {} pending transactions at {}", source, pending, time);
+                let row = MempoolRow {
+                    source: source.to_string(),
+                    time,
+                    pending,
+                };
+                emit(MetricRow::Mempool(row))?;
+            }
+        }
+    }
+
+    if metrics.catchup_count {
+        eprintln!("{}: {} catch-up events", source, catchups);
+    }
+    if let Some(open) = catchup {
+        eprintln!(
+            "{}: Catch-up started at {} is still open at the end of the log",
+            source, open.start
+        );
+        emit(MetricRow::CatchupDuration(open.row(
+            source,
+            None,
+            saw_arrivals,
+        )))?;
+    }
+    if metrics.peer_churn {
+        eprintln!(
+            "{}: {} peer connects and {} disconnects",
+            source, connects, disconnects
+        );
+    }
+    let when = if found_first_blocks {
+        "before the first blocks were measured "
+    } else if past_until {
+        "before --until "
+    } else {
+        ""
+    };
+    report_unended(source, &metrics.rules, &mut started, when);
+    if let Some((rule, n)) = metrics.first_blocks {
+        if found_first_blocks {
+            eprintln!("{}: Stopped reading after the first {} blocks", source, n);
+        } else {
+            eprintln!(
+                "{}: Warning: found only {} of the first {} blocks",
+                source, counts[rule], n
+            );
+        }
+    }
+    if metrics.restarts.is_some() {
+        eprintln!("{}: {} restarts", source, starts.saturating_sub(1));
+    }
+    Ok(())
+}
+
+/// Report and forget the measurements of the `rules` that have `started` but
+/// not ended, `when` is put before their keys.
+fn report_unended(source: &str, rules: &[Rule], started: &mut [rules::Started], when: &str) {
+    for (rule, started) in rules.iter().zip(started) {
+        if !started.is_empty() {
+            let keys = started.drain();
+            eprintln!(
+                "{}: {} {} measurements did not end {}{}",
+                source,
+                keys.len(),
+                rule.name,
+                when,
+                keys.join(", ")
+            );
+        }
+    }
+}
+
+/// Identify a measurement by its key, or by its number if it has none.
+fn measurement(key: &str, number: usize) -> String {
+    if key.is_empty() {
+        number.to_string()
+    } else {
+        key.to_string()
+    }
+}
+
+/// Write the rows of a metric to `out` with `suffix` appended to its stem,
+/// or to stdout if there is no `out`.
+fn open_output(
+    out: Option<&Path>,
+    suffix: &str,
+    format: Format,
+    follow: bool,
+) -> anyhow::Result<OutputSink> {
+    let path = out.map(|out| output_sink::with_suffix(out, suffix));
+    let sink = OutputSink::open(path.as_deref(), format)?;
+    if follow {
+        sink.streaming()
+    } else {
+        Ok(sink)
+    }
+}
+
+/// Write `row` to the output of a metric, if it is selected.
+fn write_row(output: &mut Option<OutputSink>, row: impl Serialize) -> anyhow::Result<()> {
+    match output {
+        Some(output) => output.write(row),
+        None => Ok(()),
+    }
+}
+
+/// The whitespace separated word following `prefix` in `line`.
+fn word_after<'a>(line: &'a str, prefix: &str) -> Option<&'a str> {
+    let (_, rest) = line.split_once(prefix)?;
+    rest.split_whitespace().next()
+}
+
+/// The number of pending transactions reported by `line`, if it matches one
+/// of the `MEMPOOL_PATTERNS`.
+fn pending_count(line: &str) -> Option<u64> {
+    let (_, rest) = MEMPOOL_PATTERNS
+        .iter()
+        .find_map(|pattern| line.split_once(pattern))?;
+    rest.split(|c: char| !c.is_ascii_digit())
+        .find(|digits| !digits.is_empty())?
+        .parse()
+        .ok()
+}
+
+/// Parse the timestamp at the start of `log_line`. It is the first `length`
+/// bytes of the line if given, otherwise everything up to the first
+/// whitespace without a trailing colon.
+fn extract_timestamp(log_line: &str, length: Option<usize>) -> anyhow::Result<DateTime<Utc>> {
+    let ts_str = match length {
+        Some(length) => log_line.get(..length).context(format!(
+            "the line does not start with {} bytes of text",
+            length
+        ))?,
+        None => log_line
+            .split(char::is_whitespace)
+            .next()
+            .unwrap_or_default()
+            .trim_end_matches(':'),
+    };
+    DateTime::from_str(ts_str).context(format!("cannot parse DateTime from {:?}", ts_str))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamp_up_to_whitespace() {
+        let expected: DateTime<Utc> = DateTime::from_str("2022-05-22T10:45:55.229618571Z").unwrap();
+        for line in [
+            "2022-05-22T10:45:55.229618571Z DEBUG Skov: Received block",
+            "2022-05-22T10:45:55.229618571Z: DEBUG: Skov: Received block",
+            "2022-05-22T10:45:55.229618571Z",
+        ] {
+            assert_eq!(extract_timestamp(line, None).unwrap(), expected);
+        }
+        let short: DateTime<Utc> = DateTime::from_str("2022-05-22T10:45:55Z").unwrap();
+        assert_eq!(
+            extract_timestamp("2022-05-22T10:45:55Z INFO", None).unwrap(),
+            short
+        );
+    }
+
+    #[test]
+    fn timestamp_with_length() {
+        let expected: DateTime<Utc> = DateTime::from_str("2022-05-22T10:45:55.229618571Z").unwrap();
+        let line = "2022-05-22T10:45:55.229618571ZDEBUG";
+        assert_eq!(extract_timestamp(line, Some(30)).unwrap(), expected);
+    }
+
+    #[test]
+    fn pending_count_after_pattern() {
+        assert_eq!(
+            pending_count("2022-05-22T10:45:55Z INFO: Pending transactions: 42"),
+            Some(42)
+        );
+        assert_eq!(
+            pending_count(
+                "2022-05-22T10:45:55Z DEBUG Number of pending transactions 7 in 3 accounts"
+            ),
+            Some(7)
+        );
+        assert_eq!(
+            pending_count("2022-05-22T10:45:55Z INFO: 42 transactions"),
+            None
+        );
+        assert_eq!(pending_count("Pending transactions: none"), None);
+    }
+
+    #[test]
+    fn truncated_and_multibyte_lines_are_errors() {
+        for line in [
+            "",
+            "2022-05-22T10:45",
+            "   at Skov.hs:42",
+            "Skov: Received block",
+        ] {
+            assert!(extract_timestamp(line, None).is_err(), "{:?}", line);
+            assert!(extract_timestamp(line, Some(30)).is_err(), "{:?}", line);
+        }
+        // byte 30 is inside the multibyte character.
+        let multibyte = "2022-05-22T10:45:55.22961857\u{00e9} DEBUG";
+        assert!(extract_timestamp(multibyte, None).is_err());
+        assert!(extract_timestamp(multibyte, Some(30)).is_err());
+        assert!(extract_timestamp("\u{1f980}\u{1f980}\u{1f980}", Some(30)).is_err());
+    }
+}
@@ -0,0 +1,117 @@
+use anyhow::Context;
+use flate2::read::GzDecoder;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Reads a log file a line at a time. When following, the end of the file is
+/// polled for new lines instead of ending the iteration, like `tail -f`. Files
+/// ending in `.gz` are decompressed.
+pub(crate) struct LogReader {
+    path: PathBuf,
+    reader: Box<dyn BufRead>,
+    /// The number of bytes read from the current file.
+    position: u64,
+    /// How often to check for new lines at the end of the file, if following.
+    follow: Option<Duration>,
+    /// A line that has not been completely written yet.
+    pending: Vec<u8>,
+}
+
+impl LogReader {
+    pub(crate) fn open(path: PathBuf, follow: Option<Duration>) -> anyhow::Result<Self> {
+        let gzipped = path.extension().is_some_and(|extension| extension == "gz");
+        anyhow::ensure!(
+            !(gzipped && follow.is_some()),
+            "a gzipped log file cannot be followed."
+        );
+        let file = File::open(&path).context("cannot open log file")?;
+        let reader: Box<dyn BufRead> = if gzipped {
+            Box::new(BufReader::new(GzDecoder::new(file)))
+        } else {
+            Box::new(BufReader::new(file))
+        };
+        Ok(LogReader {
+            path,
+            reader,
+            position: 0,
+            follow,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Start over if the file is now smaller than what was read, because it was
+    /// truncated or replaced, e.g. when the log was rotated.
+    fn reopen_if_replaced(&mut self) -> anyhow::Result<()> {
+        let len = std::fs::metadata(&self.path)
+            .context("cannot read log file")?
+            .len();
+        if len < self.position {
+            let file = File::open(&self.path).context("cannot open log file")?;
+            self.reader = Box::new(BufReader::new(file));
+            self.position = 0;
+            self.pending.clear();
+        }
+        Ok(())
+    }
+
+    /// The next line, with the bytes that are not valid UTF-8 replaced, so
+    /// that a corrupted line does not end the analysis of the file.
+    fn next_line(&mut self) -> anyhow::Result<Option<String>> {
+        loop {
+            let read = self
+                .reader
+                .read_until(b'\n', &mut self.pending)
+                .context("cannot read log file")?;
+            self.position += read as u64;
+            if self.pending.ends_with(b"\n") {
+                let line = String::from_utf8_lossy(&self.pending)
+                    .trim_end_matches(['\n', '\r'])
+                    .to_string();
+                self.pending.clear();
+                return Ok(Some(line));
+            }
+            // at the end of the file, a partial line is only complete if the
+            // file is not followed.
+            match self.follow {
+                Some(interval) if read == 0 => {
+                    std::thread::sleep(interval);
+                    self.reopen_if_replaced()?;
+                }
+                Some(_) => {}
+                None if self.pending.is_empty() => return Ok(None),
+                None => {
+                    let line = String::from_utf8_lossy(&self.pending).into_owned();
+                    self.pending.clear();
+                    return Ok(Some(line));
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for LogReader {
+    type Item = anyhow::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_line().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_that_are_not_utf8_are_read_lossily() {
+        let path =
+            std::env::temp_dir().join(format!("log-analyzer-reader-{}.log", std::process::id()));
+        std::fs::write(&path, b"first\r\nbad \xff byte\nlast").unwrap();
+        let lines = LogReader::open(path.clone(), None)
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(lines.unwrap(), ["first", "bad \u{fffd} byte", "last"]);
+    }
+}
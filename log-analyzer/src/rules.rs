@@ -0,0 +1,218 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+};
+
+/// The name of the built-in rule measuring the block execution times.
+pub(crate) const BLOCK_EXECUTION: &str = "BlockExecution";
+
+/// A metric measured as the time from a line matching `start` until the next
+/// line matching `end`. If the patterns capture a group named `key`, like the
+/// hash of a block, a start is only ended by an end with the same key, so
+/// overlapping measurements are paired correctly. See [`Started`] for the
+/// lines without a key.
+pub(crate) struct Rule {
+    pub(crate) name: String,
+    pub(crate) start: Regex,
+    pub(crate) end: Regex,
+}
+
+impl Rule {
+    /// The key of a measurement started by `line`, if it matches.
+    pub(crate) fn start_key<'a>(&self, line: &'a str) -> Option<&'a str> {
+        key(&self.start, line)
+    }
+
+    /// The key of a measurement ended by `line`, if it matches.
+    pub(crate) fn end_key<'a>(&self, line: &'a str) -> Option<&'a str> {
+        key(&self.end, line)
+    }
+}
+
+/// The `key` group of `pattern` in `line`, or an empty key if it matches
+/// without one.
+fn key<'a>(pattern: &Regex, line: &'a str) -> Option<&'a str> {
+    let captures = pattern.captures(line)?;
+    Some(captures.name("key").map_or("", |key| key.as_str()))
+}
+
+/// The start times of the measurements of a rule that have not ended yet.
+/// A start with a key is ended by an end with the same key. The starts
+/// without a key are ended in the order they started, by an end without a
+/// key or by an end whose key has not started, e.g. when only the end line
+/// names the block.
+#[derive(Default)]
+pub(crate) struct Started {
+    keyed: HashMap<String, DateTime<Utc>>,
+    unkeyed: VecDeque<DateTime<Utc>>,
+}
+
+impl Started {
+    /// Whether a start with `key` is already open, so that a repeated start,
+    /// e.g. of a block received twice, can be ignored.
+    pub(crate) fn contains(&self, key: &str) -> bool {
+        !key.is_empty() && self.keyed.contains_key(key)
+    }
+
+    pub(crate) fn insert(&mut self, key: &str, time: DateTime<Utc>) {
+        if key.is_empty() {
+            self.unkeyed.push_back(time);
+        } else {
+            self.keyed.insert(key.to_string(), time);
+        }
+    }
+
+    /// Whether an end with `key` ends an open start.
+    pub(crate) fn ends(&self, key: &str) -> bool {
+        self.keyed.contains_key(key) || !self.unkeyed.is_empty()
+    }
+
+    /// Remove the start ended by an end with `key`, and return its time.
+    pub(crate) fn end(&mut self, key: &str) -> Option<DateTime<Utc>> {
+        self.keyed.remove(key).or_else(|| self.unkeyed.pop_front())
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.keyed.is_empty() && self.unkeyed.is_empty()
+    }
+
+    /// Forget all starts, and return their keys, sorted, with an empty key
+    /// for every start without one.
+    pub(crate) fn drain(&mut self) -> Vec<String> {
+        let mut keys: Vec<String> = self.keyed.drain().map(|(key, _)| key).collect();
+        keys.sort();
+        keys.extend(self.unkeyed.drain(..).map(|_| String::new()));
+        keys
+    }
+}
+
+/// The contents of a rules file, e.g.
+///
+/// ```toml
+/// [[rule]]
+/// name = "BlockExecution"
+/// start = "Skov: Received block"
+/// end = "arrived"
+/// ```
+#[derive(Deserialize)]
+struct RulesFile {
+    #[serde(default)]
+    rule: Vec<RuleConfig>,
+}
+
+#[derive(Deserialize)]
+struct RuleConfig {
+    name: String,
+    start: String,
+    end: String,
+}
+
+impl RuleConfig {
+    fn compile(self) -> anyhow::Result<Rule> {
+        let start = Regex::new(&self.start)
+            .context(format!("invalid start pattern of rule {}", self.name))?;
+        let end =
+            Regex::new(&self.end).context(format!("invalid end pattern of rule {}", self.name))?;
+        Ok(Rule {
+            name: self.name,
+            start,
+            end,
+        })
+    }
+}
+
+/// The rules to measure. The built-in BlockExecution rule is used if
+/// `block_execution` is selected, unless the rules file at `path` replaces it.
+/// The other rules of the file are always measured.
+pub(crate) fn load(path: Option<&Path>, block_execution: bool) -> anyhow::Result<Vec<Rule>> {
+    let mut configs = vec![RuleConfig {
+        name: BLOCK_EXECUTION.to_string(),
+        start: r"Skov: Received block(?:\s+(?P<key>[0-9a-f]{64}))?".to_string(),
+        end: r"Block (?P<key>[0-9a-f]{64}) arrived|arrived".to_string(),
+    }];
+    if let Some(path) = path {
+        let contents = std::fs::read_to_string(path)
+            .context(format!("cannot read rules file {}", path.display()))?;
+        let file: RulesFile = toml::from_str(&contents)
+            .context(format!("cannot parse rules file {}", path.display()))?;
+        for rule in file.rule {
+            anyhow::ensure!(
+                !rule.name.is_empty(),
+                "the rules in {} must have a name",
+                path.display()
+            );
+            configs.retain(|config| config.name != rule.name);
+            configs.push(rule);
+        }
+    }
+    configs
+        .into_iter()
+        .filter(|config| block_execution || config.name != BLOCK_EXECUTION)
+        .map(RuleConfig::compile)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(secs, 0).unwrap()
+    }
+
+    #[test]
+    fn keyed_starts_are_ended_by_their_key() {
+        let mut started = Started::default();
+        started.insert("a", at(1));
+        started.insert("b", at(2));
+        assert!(started.contains("a"));
+        assert!(!started.ends("c"));
+        assert_eq!(started.end("b"), Some(at(2)));
+        assert_eq!(started.end("a"), Some(at(1)));
+        assert!(started.is_empty());
+    }
+
+    #[test]
+    fn unkeyed_starts_are_ended_in_order() {
+        let mut started = Started::default();
+        started.insert("", at(1));
+        started.insert("a", at(2));
+        started.insert("", at(3));
+        assert!(!started.contains(""));
+        // an end without a start of its key takes the oldest unkeyed start.
+        assert_eq!(started.end("b"), Some(at(1)));
+        assert_eq!(started.end("a"), Some(at(2)));
+        assert_eq!(started.end(""), Some(at(3)));
+        assert!(!started.ends(""));
+        assert_eq!(started.end(""), None);
+    }
+
+    #[test]
+    fn drain_lists_the_unended_keys() {
+        let mut started = Started::default();
+        started.insert("b", at(1));
+        started.insert("", at(2));
+        started.insert("a", at(3));
+        assert_eq!(started.drain(), ["a", "b", ""]);
+        assert!(started.is_empty());
+    }
+
+    #[test]
+    fn block_execution_pairs_a_start_without_hash() {
+        let rules = load(None, true).unwrap();
+        let rule = &rules[0];
+        let hash = "ab".repeat(32);
+        let start = rule.start_key("Skov: Received block").unwrap();
+        let end_line = format!("Block {} arrived", hash);
+        let end = rule.end_key(&end_line).unwrap();
+        assert_eq!((start, end), ("", hash.as_str()));
+        let mut started = Started::default();
+        started.insert(start, at(1));
+        assert_eq!(started.end(end), Some(at(1)));
+    }
+}
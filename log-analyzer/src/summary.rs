@@ -0,0 +1,202 @@
+use crate::rules::Rule;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// The number of buckets of a histogram.
+const HISTOGRAM_BUCKETS: i64 = 10;
+
+/// The length of the bar of the fullest bucket of a histogram.
+const HISTOGRAM_WIDTH: usize = 50;
+
+/// Statistics of a time in milliseconds.
+struct Stats {
+    count: usize,
+    min: i64,
+    max: i64,
+    mean: f64,
+    median: i64,
+    p90: i64,
+    p95: i64,
+    p99: i64,
+}
+
+impl Stats {
+    fn new(sorted: &[i64]) -> Option<Stats> {
+        let count = sorted.len();
+        Some(Stats {
+            count,
+            min: *sorted.first()?,
+            max: *sorted.last()?,
+            mean: sorted.iter().sum::<i64>() as f64 / count as f64,
+            median: percentile(sorted, 0.5),
+            p90: percentile(sorted, 0.9),
+            p95: percentile(sorted, 0.95),
+            p99: percentile(sorted, 0.99),
+        })
+    }
+}
+
+/// The nearest-rank percentile `p` of the non-empty `sorted` values.
+fn percentile(sorted: &[i64], p: f64) -> i64 {
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.saturating_sub(1)]
+}
+
+/// Write a table with the statistics of the `times` measured by every rule,
+/// keyed by the index of the rule and the log file, followed by a histogram of
+/// each if `histogram` is set.
+pub(crate) fn write_summary(
+    rules: &[Rule],
+    mut times: BTreeMap<(usize, String), Vec<i64>>,
+    histogram: bool,
+    out: &mut dyn Write,
+) -> anyhow::Result<()> {
+    for values in times.values_mut() {
+        values.sort_unstable();
+    }
+    writeln!(
+        out,
+        "{:<30} {:<18} {:>8} {:>10} {:>10} {:>10} {:>11} {:>10} {:>10} {:>10}",
+        "Source",
+        "Rule",
+        "Count",
+        "Min (ms)",
+        "Max (ms)",
+        "Mean (ms)",
+        "Median (ms)",
+        "P90 (ms)",
+        "P95 (ms)",
+        "P99 (ms)"
+    )?;
+    for ((rule, source), values) in &times {
+        if let Some(stats) = Stats::new(values) {
+            writeln!(
+                out,
+                "{:<30} {:<18} {:>8} {:>10} {:>10} {:>10.1} {:>11} {:>10} {:>10} {:>10}",
+                source,
+                rules[*rule].name,
+                stats.count,
+                stats.min,
+                stats.max,
+                stats.mean,
+                stats.median,
+                stats.p90,
+                stats.p95,
+                stats.p99
+            )?;
+        }
+    }
+    if histogram {
+        for ((rule, source), values) in &times {
+            writeln!(out)?;
+            writeln!(out, "{} {} (millis)", source, rules[*rule].name)?;
+            write_histogram(values, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write the `sorted` values as bars of `#` over buckets of equal width.
+fn write_histogram(sorted: &[i64], out: &mut dyn Write) -> anyhow::Result<()> {
+    let (Some(&min), Some(&max)) = (sorted.first(), sorted.last()) else {
+        return Ok(());
+    };
+    // the buckets are rounded up so that the largest value falls into the last.
+    let width = (max - min) / HISTOGRAM_BUCKETS + 1;
+    let mut counts = vec![0; HISTOGRAM_BUCKETS as usize];
+    for value in sorted {
+        counts[((value - min) / width) as usize] += 1;
+    }
+    let fullest = counts.iter().copied().max().unwrap_or_default();
+    for (bucket, count) in counts.into_iter().enumerate() {
+        let start = min + bucket as i64 * width;
+        writeln!(
+            out,
+            "{:>10} - {:<10} {:>8} {}",
+            start,
+            start + width - 1,
+            count,
+            "#".repeat(count * HISTOGRAM_WIDTH / fullest)
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    /// The count of every bucket of the histogram of `sorted`.
+    fn bucket_counts(sorted: &[i64]) -> Vec<usize> {
+        let mut out = vec![];
+        write_histogram(sorted, &mut out).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| line.split_whitespace().nth(3).unwrap().parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn percentiles_take_the_nearest_rank() {
+        let sorted: Vec<i64> = (1..=100).collect();
+        assert_eq!(percentile(&sorted, 0.5), 50);
+        assert_eq!(percentile(&sorted, 0.9), 90);
+        assert_eq!(percentile(&sorted, 0.99), 99);
+        let sorted: Vec<i64> = (1..=10).collect();
+        assert_eq!(percentile(&sorted, 0.5), 5);
+        assert_eq!(percentile(&sorted, 0.9), 9);
+        assert_eq!(percentile(&sorted, 0.99), 10);
+    }
+
+    #[test]
+    fn a_single_time_is_every_statistic() {
+        let stats = Stats::new(&[42]).unwrap();
+        assert_eq!(stats.count, 1);
+        assert_eq!((stats.min, stats.max, stats.median), (42, 42, 42));
+        assert_eq!((stats.p90, stats.p95, stats.p99), (42, 42, 42));
+        assert_eq!(stats.mean, 42.0);
+        assert!(Stats::new(&[]).is_none());
+    }
+
+    #[test]
+    fn histogram_buckets_cover_the_range() {
+        let sorted: Vec<i64> = (0..20).collect();
+        assert_eq!(bucket_counts(&sorted), [2; 10]);
+        // the largest value falls into the last bucket.
+        assert_eq!(bucket_counts(&[0, 100]), [1, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(bucket_counts(&[7, 7, 7]), [3, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(bucket_counts(&[]).is_empty());
+    }
+
+    #[test]
+    fn summary_has_a_line_per_rule_and_source() {
+        let rules = [Rule {
+            name: "BlockExecution".to_string(),
+            start: Regex::new("start").unwrap(),
+            end: Regex::new("end").unwrap(),
+        }];
+        let times = BTreeMap::from([((0, "peer-0.log".to_string()), vec![30, 10, 20])]);
+        let mut out = vec![];
+        write_summary(&rules, times, false, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[1].split_whitespace().collect::<Vec<_>>(),
+            [
+                "peer-0.log",
+                "BlockExecution",
+                "3",
+                "10",
+                "30",
+                "20.0",
+                "20",
+                "30",
+                "30",
+                "30"
+            ]
+        );
+    }
+}
@@ -0,0 +1,259 @@
+//! A small client of the gRPC interface of a node, shared by the tools. It
+//! wraps the client of the SDK, knows the node's admin token, connects over
+//! TLS for `https` endpoints and retries failed queries with exponential
+//! backoff.
+use anyhow::Context;
+use concordium_rust_sdk::endpoints;
+use concordium_rust_sdk::types::{hashes::BlockHash, queries};
+use std::future::Future;
+use std::time::Duration;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint};
+
+/// The token the nodes expect in the `authentication` header by default.
+pub const DEFAULT_TOKEN: &str = "rpcadmin";
+
+/// The delay before the first retry of a failed query, doubled for every
+/// further retry.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// The gRPC interface of a node and the token to access it.
+#[derive(Clone)]
+pub struct NodeEndpoint {
+    endpoint: Endpoint,
+    token: String,
+}
+
+impl NodeEndpoint {
+    /// Use TLS for `https` endpoints, verified with `ca_cert` if given and
+    /// otherwise with the system roots.
+    pub fn new(
+        endpoint: Endpoint,
+        token: &str,
+        ca_cert: Option<&Certificate>,
+    ) -> anyhow::Result<Self> {
+        let endpoint = if endpoint.uri().scheme_str() == Some("https") {
+            let mut tls = ClientTlsConfig::new();
+            if let Some(host) = endpoint.uri().host() {
+                tls = tls.domain_name(host);
+            }
+            if let Some(ca_cert) = ca_cert {
+                tls = tls.ca_certificate(ca_cert.clone());
+            }
+            let uri = endpoint.uri().to_string();
+            endpoint
+                .tls_config(tls)
+                .context(format!("cannot configure TLS for {}", uri))?
+        } else {
+            endpoint
+        };
+        Ok(NodeEndpoint {
+            endpoint,
+            token: token.to_string(),
+        })
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn uri(&self) -> String {
+        self.endpoint.uri().to_string()
+    }
+}
+
+/// Run `query` with a clone of `client`. If it fails it is retried up to
+/// `max_retries` times with exponential backoff, replacing the client with a
+/// new one from `connect` in case the connection was dropped. Every retry is
+/// reported to `notify`, so the caller decides where the notices go.
+pub async fn retry<C, T, E, F, Fut, G, ConnectFut>(
+    client: &mut C,
+    endpoint: &NodeEndpoint,
+    max_retries: u32,
+    connect: G,
+    notify: &(dyn Fn(&str) + Sync),
+    mut query: F,
+) -> anyhow::Result<T>
+where
+    C: Clone,
+    F: FnMut(C) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    G: Fn(NodeEndpoint) -> ConnectFut,
+    ConnectFut: Future<Output = anyhow::Result<C>>,
+    anyhow::Error: From<E>,
+{
+    let mut attempt = 0;
+    loop {
+        match query(client.clone()).await {
+            Ok(result) => return Ok(result),
+            Err(e) if attempt < max_retries => {
+                let delay = RETRY_DELAY * 2u32.pow(attempt.min(6));
+                notify(&format!(
+                    "Query to {} failed: {}. Retrying in {:?}.",
+                    endpoint.uri(),
+                    anyhow::Error::from(e),
+                    delay
+                ));
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+                if let Ok(new_client) = connect(endpoint.clone()).await {
+                    *client = new_client;
+                }
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Connect to `endpoint` with `connect`, retrying up to `max_retries` times
+/// with exponential backoff, e.g. while the node is starting. Every retry is
+/// reported to `notify`.
+pub async fn connect_with_retry<C, G, ConnectFut>(
+    endpoint: &NodeEndpoint,
+    max_retries: u32,
+    connect: G,
+    notify: &(dyn Fn(&str) + Sync),
+) -> anyhow::Result<C>
+where
+    G: Fn(NodeEndpoint) -> ConnectFut,
+    ConnectFut: Future<Output = anyhow::Result<C>>,
+{
+    let mut attempt = 0;
+    loop {
+        match connect(endpoint.clone()).await {
+            Ok(client) => return Ok(client),
+            Err(e) if attempt < max_retries => {
+                let delay = RETRY_DELAY * 2u32.pow(attempt.min(6));
+                notify(&format!(
+                    "Cannot connect to {}: {:#}. Retrying in {:?}.",
+                    endpoint.uri(),
+                    e,
+                    delay
+                ));
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e.context(format!("cannot connect to {}", endpoint.uri()))),
+        }
+    }
+}
+
+/// A client of a node: the client of the SDK with typed helpers for the
+/// queries the tools share.
+#[derive(Clone)]
+pub struct Client {
+    sdk: endpoints::Client,
+    /// For the queries whose JSON the SDK does not return as sent.
+    grpc: tonic::client::Grpc<Channel>,
+    token: String,
+}
+
+// the messages of the queries.
+#[derive(Clone, PartialEq, prost::Message)]
+struct BlockHashRequest {
+    #[prost(string, tag = "1")]
+    block_hash: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct JsonResponse {
+    #[prost(string, tag = "1")]
+    value: String,
+}
+
+impl Client {
+    /// Connect to the node at `endpoint`.
+    pub async fn connect(endpoint: NodeEndpoint) -> anyhow::Result<Self> {
+        let sdk =
+            endpoints::Client::connect(endpoint.endpoint.clone(), endpoint.token.clone()).await?;
+        let channel = endpoint.endpoint.connect().await?;
+        Ok(Client {
+            sdk,
+            grpc: tonic::client::Grpc::new(channel),
+            token: endpoint.token,
+        })
+    }
+
+    /// The client of the SDK, for the queries without a helper here.
+    pub fn sdk(&mut self) -> &mut endpoints::Client {
+        &mut self.sdk
+    }
+
+    /// Call the query at `path` of the `P2P` service, which answers in JSON.
+    async fn query_json<M>(&mut self, path: &'static str, message: M) -> anyhow::Result<String>
+    where
+        M: prost::Message + Send + Sync + 'static,
+    {
+        let response: JsonResponse = self.call(path, message).await?;
+        Ok(response.value)
+    }
+
+    /// Call the method at `path` of the `P2P` service.
+    async fn call<M, R>(&mut self, path: &'static str, message: M) -> anyhow::Result<R>
+    where
+        M: prost::Message + Send + Sync + 'static,
+        R: prost::Message + Default + Send + Sync + 'static,
+    {
+        self.grpc.ready().await?;
+        let mut request = tonic::Request::new(message);
+        request
+            .metadata_mut()
+            .insert("authentication", self.token.parse()?);
+        let response: tonic::Response<R> = self
+            .grpc
+            .unary(
+                request,
+                PathAndQuery::from_static(path),
+                tonic::codec::ProstCodec::default(),
+            )
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    pub async fn get_consensus_status(&mut self) -> anyhow::Result<queries::ConsensusInfo> {
+        Ok(self.sdk.get_consensus_status().await?)
+    }
+
+    /// The info of the block with `block_hash`, or `None` if the node does not
+    /// have it, e.g. because it is pruned.
+    pub async fn get_block_info(
+        &mut self,
+        block_hash: &BlockHash,
+    ) -> anyhow::Result<Option<queries::BlockInfo>> {
+        match self.sdk.get_block_info(block_hash).await {
+            Ok(block_info) => Ok(Some(block_info)),
+            Err(endpoints::QueryError::NotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The summary of the block with `block_hash` as the JSON the node sends,
+    /// or `None` if the node does not know the block or does not serve block
+    /// summaries. The SDK only returns the summary parsed.
+    pub async fn get_block_summary_json(
+        &mut self,
+        block_hash: &BlockHash,
+    ) -> anyhow::Result<Option<String>> {
+        let request = BlockHashRequest {
+            block_hash: block_hash.to_string(),
+        };
+        match self
+            .query_json("/concordium.P2P/GetBlockSummary", request)
+            .await
+        {
+            Ok(json) if json == "null" => Ok(None),
+            Ok(json) => Ok(Some(json)),
+            Err(e)
+                if e.downcast_ref::<tonic::Status>()
+                    .is_some_and(|status| status.code() == tonic::Code::Unimplemented) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
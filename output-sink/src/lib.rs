@@ -0,0 +1,171 @@
+//! The output of the rows of the tools, shared by `block-analyzer`,
+//! `process-metrics` and `log-analyzer`. Rows are written one at a time as
+//! CSV, JSON or JSONL, to a file or to stdout.
+use anyhow::Context;
+use clap::arg_enum;
+use serde::Serialize;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+arg_enum! {
+    /// The format the rows are written in.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Format {
+        Csv,
+        // a single JSON array of all rows.
+        Json,
+        // one JSON object per line.
+        Jsonl,
+    }
+}
+
+/// The file next to `path` with `suffix` appended to its stem, for a second
+/// table of rows, e.g. `foo-tps.csv` for `foo.csv`.
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_owned();
+    name.push(suffix);
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+/// Writes rows of any `Serialize` type one at a time. The header of a CSV
+/// file is written with the first row.
+pub struct OutputSink {
+    rows: Rows,
+    /// Flush every row as it is written, when writing to stdout or when the
+    /// rows are read while they are written.
+    flush: bool,
+}
+
+enum Rows {
+    Csv(Box<csv::Writer<Box<dyn Write>>>),
+    Json {
+        out: Box<dyn Write>,
+        /// Whether the opening bracket of the array was written.
+        started: bool,
+    },
+    Jsonl(Box<dyn Write>),
+}
+
+impl OutputSink {
+    /// Write the rows to the file `path`, overwriting it, or to stdout if there
+    /// is no `path`.
+    pub fn open(path: Option<&Path>, format: Format) -> anyhow::Result<Self> {
+        match path {
+            Some(path) => {
+                let file = fs::File::create(path)
+                    .context(format!("cannot create output file {}.", path.display()))?;
+                Ok(Self::new(
+                    Box::new(std::io::BufWriter::new(file)),
+                    format,
+                    true,
+                    false,
+                ))
+            }
+            None => Ok(Self::stdout(format)),
+        }
+    }
+
+    /// Write the rows to stdout, flushing every row.
+    pub fn stdout(format: Format) -> Self {
+        Self::new(Box::new(std::io::stdout()), format, true, true)
+    }
+
+    /// Write the rows to stderr, flushing every row, e.g. for a second table
+    /// when the main rows go to stdout.
+    pub fn stderr(format: Format) -> Self {
+        Self::new(Box::new(std::io::stderr()), format, true, true)
+    }
+
+    /// Add the rows to the end of the file `path`. A CSV file that is not empty
+    /// is assumed to have its header already, so it is not written again.
+    pub fn append(path: &Path, format: Format) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            format != Format::Json,
+            "cannot append to a JSON array, use csv or jsonl"
+        );
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .context(format!("cannot open output file {}.", path.display()))?;
+        let header = file.metadata()?.len() == 0;
+        Ok(Self::new(
+            Box::new(std::io::BufWriter::new(file)),
+            format,
+            header,
+            false,
+        ))
+    }
+
+    fn new(out: Box<dyn Write>, format: Format, header: bool, flush: bool) -> Self {
+        let rows = match format {
+            Format::Csv => Rows::Csv(Box::new(
+                csv::WriterBuilder::new()
+                    .has_headers(header)
+                    .from_writer(out),
+            )),
+            Format::Json => Rows::Json {
+                out,
+                started: false,
+            },
+            Format::Jsonl => Rows::Jsonl(out),
+        };
+        OutputSink { rows, flush }
+    }
+
+    /// Flush every row as it is written, e.g. when following a log. A JSON
+    /// array is only complete once it is finished, so it cannot be streamed.
+    pub fn streaming(mut self) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            !matches!(self.rows, Rows::Json { .. }),
+            "the rows are written one at a time, use --format csv or jsonl"
+        );
+        self.flush = true;
+        Ok(self)
+    }
+
+    pub fn write(&mut self, row: impl Serialize) -> anyhow::Result<()> {
+        match &mut self.rows {
+            Rows::Csv(writer) => {
+                writer.serialize(row).context("Unable to write csv row")?;
+                if self.flush {
+                    writer.flush()?;
+                }
+            }
+            Rows::Json { out, started } => {
+                out.write_all(if *started { b",\n" } else { b"[\n" })?;
+                *started = true;
+                serde_json::to_writer(&mut *out, &row)?;
+                if self.flush {
+                    out.flush()?;
+                }
+            }
+            Rows::Jsonl(out) => {
+                serde_json::to_writer(&mut *out, &row)?;
+                writeln!(out)?;
+                if self.flush {
+                    out.flush()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Close the JSON array and flush the remaining rows.
+    pub fn finish(self) -> anyhow::Result<()> {
+        match self.rows {
+            Rows::Csv(mut writer) => writer.flush()?,
+            Rows::Json { mut out, started } => {
+                out.write_all(if started { b"\n]\n" } else { b"[]\n" })?;
+                out.flush()?;
+            }
+            Rows::Jsonl(mut out) => out.flush()?,
+        }
+        Ok(())
+    }
+}
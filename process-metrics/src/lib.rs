@@ -0,0 +1,821 @@
+use anyhow::Context;
+use output_sink::{Format, OutputSink};
+use serde_derive::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
+use sysinfo::{CpuExt, Pid, ProcessExt, System, SystemExt};
+use timestamp::{TimeFormat, Timestamp};
+
+mod prometheus;
+mod rts;
+mod timestamp;
+
+#[derive(Serialize)]
+struct Row {
+    #[serde(rename = "Label")]
+    label: Option<String>,
+    #[serde(rename = "Time")]
+    time: Timestamp,
+    #[serde(rename = "Pid")]
+    pid: String,
+    #[serde(rename = "Cpu usage (%)")]
+    cpu_usage: f32,
+    /// The cpu usage divided by the number of cpus, at most 100%.
+    #[serde(rename = "Cpu usage normalized (%)")]
+    cpu_usage_normalized: f32,
+    #[serde(rename = "Cpus")]
+    num_cpus: usize,
+    // the usage of the whole system, for context.
+    #[serde(rename = "System cpu usage (%)")]
+    system_cpu_usage: f32,
+    #[serde(rename = "System memory used (kb)")]
+    system_memory_used: u64,
+    #[serde(rename = "System memory total (kb)")]
+    system_memory_total: u64,
+    #[serde(rename = "Res Memory usage (kb)")]
+    res_memory_usage: u64,
+    /// Only available on Linux.
+    #[serde(rename = "Anon Memory usage (kb)")]
+    anon_memory_usage: Option<u64>,
+    /// Only available on Linux.
+    #[serde(rename = "File Memory usage (kb)")]
+    file_memory_usage: Option<u64>,
+    #[serde(rename = "Swap usage (kb)")]
+    swap_usage: Option<u64>,
+    /// Since the process started.
+    #[serde(rename = "Minor page faults")]
+    minor_page_faults: Option<u64>,
+    #[serde(rename = "Major page faults")]
+    major_page_faults: Option<u64>,
+    /// Only available on Linux, like the other columns read from /proc.
+    #[serde(rename = "Open fds")]
+    open_fds: Option<u64>,
+    #[serde(rename = "Threads")]
+    threads: Option<u64>,
+    #[serde(rename = "Disk read (kb)")]
+    disk_read: u64,
+    #[serde(rename = "Disk write (kb)")]
+    disk_write: u64,
+    /// Measured over the time since the previous sample of the process.
+    #[serde(rename = "Measured disk read rate (kb/s)")]
+    disk_read_per_sec: f64,
+    #[serde(rename = "Measured disk write rate (kb/s)")]
+    disk_write_per_sec: f64,
+    #[serde(rename = "Disk read total (kb)")]
+    disk_read_total: u64,
+    #[serde(rename = "Disk write total (kb)")]
+    disk_write_total: u64,
+    /// The network counters of the namespace of the process, only available
+    /// on Linux.
+    #[serde(rename = "Net received total (kb)")]
+    net_rx_total: Option<u64>,
+    #[serde(rename = "Net sent total (kb)")]
+    net_tx_total: Option<u64>,
+    #[serde(rename = "Measured net receive rate (kb/s)")]
+    net_rx_per_sec: Option<f64>,
+    #[serde(rename = "Measured net send rate (kb/s)")]
+    net_tx_per_sec: Option<f64>,
+    /// Only with `--rts-stats`.
+    #[serde(rename = "GC live (kb)")]
+    gc_live: Option<u64>,
+    #[serde(rename = "GC pause (ms)")]
+    gc_pause: Option<f64>,
+    #[serde(rename = "Allocation rate (kb/s)")]
+    allocation_rate: Option<f64>,
+    /// The averages over the last samples, only with `--smooth`.
+    #[serde(rename = "Smoothed cpu usage (%)")]
+    smoothed_cpu_usage: Option<f32>,
+    #[serde(rename = "Smoothed res memory usage (kb)")]
+    smoothed_memory_usage: Option<u64>,
+}
+
+#[derive(StructOpt)]
+pub struct Config {
+    #[structopt(
+        long = "pid",
+        help = "Processes to inspect, separated by commas.",
+        use_delimiter = true,
+        required_unless = "name"
+    )]
+    pids: Vec<i32>,
+    #[structopt(
+        long = "name",
+        help = "Inspect the process with this name instead of a pid. If several processes have the name, --aggregate is required to inspect all of them.",
+        conflicts_with = "pid"
+    )]
+    name: Option<String>,
+    #[structopt(
+        long = "aggregate",
+        help = "Write a single row per interval with the usage of all processes summed up."
+    )]
+    aggregate: bool,
+    #[structopt(
+        long = "time",
+        help = "Time to measure (minutes). Default is 5 minutes, 0 measures until Ctrl-C is pressed."
+    )]
+    time: Option<u64>,
+    #[structopt(
+        long = "continuous",
+        help = "Measure until Ctrl-C is pressed, the same as --time 0.",
+        conflicts_with = "time"
+    )]
+    continuous: bool,
+    #[structopt(
+        long = "interval",
+        help = "Interval between retrieving metrics. Default is 3 seconds."
+    )]
+    interval: Option<u64>,
+    #[structopt(
+        long = "out",
+        help = "File to output the rows to. If omitted they are written to stdout."
+    )]
+    out: Option<PathBuf>,
+    #[structopt(
+        long = "format",
+        help = "The format of the rows.",
+        possible_values = &Format::variants(),
+        case_insensitive = true,
+        default_value = "csv"
+    )]
+    format: Format,
+    #[structopt(
+        long = "append",
+        help = "Append the rows to the output file instead of overwriting it.",
+        requires = "out"
+    )]
+    append: bool,
+    #[structopt(long = "label", help = "Tag every row with this label.")]
+    label: Option<String>,
+    #[structopt(
+        long = "rts-stats",
+        help = "Read the garbage collector statistics of a Haskell process, like the node, from this file. The process must run with +RTS -S<file>."
+    )]
+    rts_stats: Option<PathBuf>,
+    #[structopt(
+        long = "smooth",
+        help = "Show the cpu and memory usage averaged over the last N samples, and add them as columns."
+    )]
+    smooth: Option<usize>,
+    #[structopt(
+        long = "time-format",
+        help = "Write the Time column with this chrono format string, e.g. \"%Y-%m-%d %H:%M:%S\". By default it is RFC 3339 in UTC."
+    )]
+    time_format: Option<String>,
+    #[structopt(
+        long = "epoch",
+        help = "Write the Time column as Unix seconds.",
+        conflicts_with = "time-format"
+    )]
+    epoch: bool,
+    #[structopt(
+        long = "max-rss-mb",
+        help = "Fail if the resident memory of a sample exceeds this many MB."
+    )]
+    max_rss_mb: Option<u64>,
+    #[structopt(
+        long = "max-cpu-pct",
+        help = "Fail if the cpu usage of a sample exceeds this percentage. 100% is one busy core."
+    )]
+    max_cpu_pct: Option<f32>,
+    #[structopt(
+        long = "fail-fast",
+        help = "Stop at the first sample that exceeds a limit instead of at the end of the measurement."
+    )]
+    fail_fast: bool,
+    #[structopt(
+        long = "include-children",
+        help = "Add the usage of all child processes, and their children, to the row of every process. The children are looked up again at every sample."
+    )]
+    include_children: bool,
+    #[structopt(
+        long = "prometheus",
+        help = "Also write the latest sample to this file in the Prometheus text format, for the textfile collector of node_exporter. The file is replaced at every sample."
+    )]
+    prometheus: Option<PathBuf>,
+}
+
+impl Config {
+    /// Write the rows to `out` and in `format` instead, if they are given.
+    pub fn with_output(
+        mut self,
+        out: Option<PathBuf>,
+        format: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        if let Some(out) = out {
+            self.out = Some(out);
+        }
+        if let Some(format) = format {
+            self.format = format.parse().map_err(anyhow::Error::msg)?;
+        }
+        Ok(self)
+    }
+}
+
+/// Sample the resource usage of processes and write it to a file or stdout.
+pub fn run(cfg: Config) -> anyhow::Result<()> {
+    let mut system = System::new_all();
+    let pids: Vec<Pid> = match &cfg.name {
+        Some(name) => find_processes(&system, name, cfg.aggregate)?,
+        None => cfg.pids.iter().map(|&pid| Pid::from(pid)).collect(),
+    };
+
+    let mut out = match &cfg.out {
+        Some(out) if cfg.append => OutputSink::append(out, cfg.format)?,
+        out => OutputSink::open(out.as_deref(), cfg.format)?,
+    };
+
+    let time: u64 = cfg.time.map_or(300, |time| time * 60);
+    let interval: u64 = cfg.interval.unwrap_or(3);
+    anyhow::ensure!(interval > 0, "the interval must be at least 1 second");
+    anyhow::ensure!(cfg.smooth != Some(0), "--smooth must be at least 1");
+    let time_format = TimeFormat::new(cfg.time_format.as_deref(), cfg.epoch)?;
+
+    // without a limit the measurement runs until it is interrupted.
+    let iterations = (!cfg.continuous && time > 0).then_some(time / interval);
+
+    // the rows written so far are kept when Ctrl-C is pressed.
+    let (interrupt, interrupted) = std::sync::mpsc::channel();
+    ctrlc::set_handler(move || {
+        let _ = interrupt.send(());
+    })
+    .context("cannot listen for Ctrl-C")?;
+
+    let mut rts_log = match &cfg.rts_stats {
+        Some(path) => {
+            anyhow::ensure!(
+                pids.len() == 1,
+                "--rts-stats can only be used with a single process."
+            );
+            Some(rts::RtsLog::open(path.clone())?)
+        }
+        None => None,
+    };
+
+    #[cfg(not(target_os = "linux"))]
+    eprintln!("Warning: the anon and file memory usage are only measured on Linux.");
+
+    // sysinfo measures the cpu and disk usage since the previous refresh, so
+    // every sample is taken one interval after the processes were refreshed.
+    system.refresh_processes();
+    let mut previous = vec![];
+    for &pid in &pids {
+        previous.push(Previous {
+            refreshed: Instant::now(),
+            net: net_counters(pid).ok().flatten(),
+            children: HashMap::new(),
+        });
+    }
+
+    // the last samples of every process, or of the aggregate, by pid.
+    let mut windows: HashMap<String, Window> = HashMap::new();
+    // the samples that exceeded --max-rss-mb or --max-cpu-pct.
+    let mut exceeded = vec![];
+    let mut i = 0;
+    while iterations.is_none_or(|iterations| i < iterations) {
+        if interrupted
+            .recv_timeout(Duration::from_secs(interval))
+            .is_ok()
+        {
+            break;
+        }
+        i += 1;
+        let progress = match iterations {
+            Some(iterations) => format!("{}/{}", i, iterations),
+            None => i.to_string(),
+        };
+        let time = Timestamp {
+            time: chrono::offset::Utc::now(),
+            format: time_format.clone(),
+        };
+        // all processes are refreshed at once: a refresh of a single process
+        // restarts the interval the cpu usage of the others is measured over.
+        // it also updates the system cpu usage over the same interval, and
+        // finds the children started since the previous sample.
+        system.refresh_memory();
+        system.refresh_processes();
+        let mut samples = vec![];
+        for (&pid, previous) in pids.iter().zip(&mut previous) {
+            let row = if cfg.include_children {
+                sample_tree(&system, pid, time.clone(), previous)
+            } else {
+                measure(&system, pid, time.clone(), previous)
+            };
+            // a process that is gone is reported but does not stop the others.
+            match row {
+                Ok(row) => samples.push(row),
+                Err(e) => eprintln!("{} | Pid {} | {:#}", progress, pid, e),
+            }
+        }
+        if cfg.aggregate {
+            samples = aggregate(samples).into_iter().collect();
+        }
+        let gc = rts_log.as_mut().and_then(|log| {
+            log.sample()
+                .map_err(|e| eprintln!("{} | {:#}", progress, e))
+                .ok()
+        });
+        for row in &mut samples {
+            row.label.clone_from(&cfg.label);
+            if let Some(gc) = &gc {
+                row.gc_live = gc.live;
+                row.gc_pause = Some(gc.pause_millis);
+                row.allocation_rate = Some(gc.allocation_rate);
+            }
+            if let Some(size) = cfg.smooth {
+                let window = windows.entry(row.pid.clone()).or_default();
+                let (cpu, memory) = window.push(size, row.cpu_usage, row.res_memory_usage);
+                row.smoothed_cpu_usage = Some(cpu);
+                row.smoothed_memory_usage = Some(memory);
+            }
+            // the rows may be written to stdout, so the progress goes to stderr.
+            eprintln!(
+                "{} | Pid {} | Time {} | CPU {}% | Res Mem {} MB | Anon Mem {} MB | File Mem {} | Disk Read {:.1} KB/s | Disk Write {:.1} KB/s",
+                progress,
+                row.pid,
+                time.time,
+                row.smoothed_cpu_usage.unwrap_or(row.cpu_usage),
+                row.smoothed_memory_usage.unwrap_or(row.res_memory_usage) / 1000,
+                display_mb(row.anon_memory_usage),
+                display_mb(row.file_memory_usage),
+                row.disk_read_per_sec,
+                row.disk_write_per_sec
+            );
+            let limits = exceeded_limits(&cfg, row);
+            for limit in &limits {
+                eprintln!("{} | Pid {} | Exceeded: {}", progress, row.pid, limit);
+            }
+            exceeded.extend(
+                limits.into_iter().map(|limit| {
+                    format!("sample {}, pid {}, {}: {}", i, row.pid, time.time, limit)
+                }),
+            );
+        }
+        if let Some(path) = &cfg.prometheus {
+            prometheus::write_textfile(path, &samples)?;
+        }
+        for row in samples {
+            out.write(row)?;
+        }
+        if cfg.fail_fast && !exceeded.is_empty() {
+            break;
+        }
+    }
+
+    out.finish()?;
+    anyhow::ensure!(
+        exceeded.is_empty(),
+        "the limits were exceeded {} times:\n{}",
+        exceeded.len(),
+        exceeded.join("\n")
+    );
+    Ok(())
+}
+
+/// The limits of `--max-rss-mb` and `--max-cpu-pct` that the usage of `row`
+/// exceeds. The raw sample is compared even with `--smooth`, so that a spike
+/// the averages hide still fails.
+fn exceeded_limits(cfg: &Config, row: &Row) -> Vec<String> {
+    let mut exceeded = vec![];
+    let memory = row.res_memory_usage / 1000;
+    if let Some(max) = cfg.max_rss_mb.filter(|&max| memory > max) {
+        exceeded.push(format!("res memory {} MB > {} MB", memory, max));
+    }
+    if let Some(max) = cfg.max_cpu_pct.filter(|&max| row.cpu_usage > max) {
+        exceeded.push(format!("cpu {:.1}% > {}%", row.cpu_usage, max));
+    }
+    exceeded
+}
+
+/// The pids of the processes called `name`. Several processes may only match
+/// if their usage is aggregated.
+fn find_processes(system: &System, name: &str, aggregate: bool) -> anyhow::Result<Vec<Pid>> {
+    let mut pids: Vec<Pid> = system
+        .processes_by_exact_name(name)
+        .map(|proc| proc.pid())
+        .collect();
+    pids.sort();
+    anyhow::ensure!(!pids.is_empty(), "No process is called {}", name);
+    anyhow::ensure!(
+        pids.len() == 1 || aggregate,
+        "Several processes are called {}: {}. Use --aggregate to inspect all of them or --pid to choose one.",
+        name,
+        pids.iter()
+            .map(|pid| pid.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    Ok(pids)
+}
+
+/// The state of a process when it was sampled the previous time.
+struct Previous {
+    refreshed: Instant,
+    /// The received and sent network bytes.
+    net: Option<(u64, u64)>,
+    /// The descendants of the process, with `--include-children`.
+    children: HashMap<Pid, Previous>,
+}
+
+/// The last samples of a process, averaged with `--smooth`.
+#[derive(Default)]
+struct Window {
+    /// The cpu usage and resident memory of every sample.
+    samples: VecDeque<(f32, u64)>,
+}
+
+impl Window {
+    /// Add a sample, keeping the last `size`, and return the average cpu usage
+    /// and resident memory.
+    fn push(&mut self, size: usize, cpu_usage: f32, memory: u64) -> (f32, u64) {
+        if self.samples.len() == size {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((cpu_usage, memory));
+        let count = self.samples.len();
+        let cpu_usage = self.samples.iter().map(|(cpu, _)| cpu).sum::<f32>() / count as f32;
+        let memory = self.samples.iter().map(|(_, memory)| memory).sum::<u64>() / count as u64;
+        (cpu_usage, memory)
+    }
+}
+
+/// Measure the resource usage of the process `pid` and all its descendants,
+/// summed up in a single row of `pid`. The processes must be refreshed first.
+fn sample_tree(
+    system: &System,
+    pid: Pid,
+    time: Timestamp,
+    previous: &mut Previous,
+) -> anyhow::Result<Row> {
+    // a child that is new is measured over the whole interval.
+    let refreshed = previous.refreshed;
+    let mut rows = vec![measure(system, pid, time.clone(), previous)?];
+    let descendants = descendants(system, pid);
+    // the children that exited are forgotten.
+    previous
+        .children
+        .retain(|child, _| descendants.contains(child));
+    for child in descendants {
+        let previous = previous.children.entry(child).or_insert(Previous {
+            refreshed,
+            net: None,
+            children: HashMap::new(),
+        });
+        // a child that exited since the refresh is left out.
+        if let Ok(row) = measure(system, child, time.clone(), previous) {
+            rows.push(row);
+        }
+    }
+    let mut row = aggregate(rows).context("Unknown pid")?;
+    row.pid = pid.to_string();
+    Ok(row)
+}
+
+/// The children of the process `pid`, their children and so on.
+fn descendants(system: &System, pid: Pid) -> Vec<Pid> {
+    let mut found = vec![pid];
+    let mut i = 0;
+    while let Some(&parent) = found.get(i) {
+        found.extend(
+            system
+                .processes()
+                .values()
+                .filter(|proc| proc.parent() == Some(parent))
+                .map(|proc| proc.pid()),
+        );
+        i += 1;
+    }
+    found.split_off(1)
+}
+
+/// Read the usage of the refreshed process `pid` since the `previous` sample.
+fn measure(
+    system: &System,
+    pid: Pid,
+    time: Timestamp,
+    previous: &mut Previous,
+) -> anyhow::Result<Row> {
+    let now = Instant::now();
+    let elapsed = now.duration_since(previous.refreshed).as_secs_f64();
+    previous.refreshed = now;
+    let proc = system.process(pid).context("Unknown pid")?;
+
+    // the columns read from /proc are left empty if they cannot be read.
+    let status = proc_status(pid).unwrap_or_else(|e| {
+        eprintln!("Pid {}: {:#}", pid, e);
+        ProcStatus::default()
+    });
+    let page_faults = page_faults(pid).unwrap_or_else(|e| {
+        eprintln!("Pid {}: {:#}", pid, e);
+        None
+    });
+    let open_fds = open_fds(pid).unwrap_or_else(|e| {
+        eprintln!("Pid {}: {:#}", pid, e);
+        None
+    });
+    let net = net_counters(pid).unwrap_or_else(|e| {
+        eprintln!("Pid {}: {:#}", pid, e);
+        None
+    });
+    let net_rate = |counter: fn((u64, u64)) -> u64| {
+        let (now, before) = (counter(net?), counter(previous.net?));
+        Some(now.saturating_sub(before) as f64 / 1000.0 / elapsed)
+    };
+    let net_rx_per_sec = net_rate(|(rx, _)| rx);
+    let net_tx_per_sec = net_rate(|(_, tx)| tx);
+    previous.net = net;
+
+    let disk_usage = proc.disk_usage();
+    let num_cpus = system.cpus().len().max(1);
+    Ok(Row {
+        label: None,
+        time,
+        pid: pid.to_string(),
+        cpu_usage: proc.cpu_usage(),
+        cpu_usage_normalized: proc.cpu_usage() / num_cpus as f32,
+        num_cpus,
+        system_cpu_usage: system.global_cpu_info().cpu_usage(),
+        system_memory_used: system.used_memory(),
+        system_memory_total: system.total_memory(),
+        res_memory_usage: proc.memory(),
+        anon_memory_usage: status.anon_memory,
+        file_memory_usage: status.file_memory,
+        swap_usage: status.swap,
+        minor_page_faults: page_faults.map(|(minor, _)| minor),
+        major_page_faults: page_faults.map(|(_, major)| major),
+        open_fds,
+        threads: status.threads,
+        disk_read: disk_usage.read_bytes / 1000,
+        disk_write: disk_usage.written_bytes / 1000,
+        disk_read_per_sec: disk_usage.read_bytes as f64 / 1000.0 / elapsed,
+        disk_write_per_sec: disk_usage.written_bytes as f64 / 1000.0 / elapsed,
+        disk_read_total: disk_usage.total_read_bytes / 1000,
+        disk_write_total: disk_usage.total_written_bytes / 1000,
+        net_rx_total: net.map(|(rx, _)| rx / 1000),
+        net_tx_total: net.map(|(_, tx)| tx / 1000),
+        net_rx_per_sec,
+        net_tx_per_sec,
+        gc_live: None,
+        gc_pause: None,
+        allocation_rate: None,
+        smoothed_cpu_usage: None,
+        smoothed_memory_usage: None,
+    })
+}
+
+/// Sum up the usage of several processes sampled at the same time. The pids
+/// are joined with `+`. The network counters are those of the first process,
+/// since processes usually share a network namespace.
+fn aggregate(rows: Vec<Row>) -> Option<Row> {
+    rows.into_iter().reduce(|sum, row| Row {
+        label: sum.label,
+        time: sum.time,
+        pid: format!("{}+{}", sum.pid, row.pid),
+        cpu_usage: sum.cpu_usage + row.cpu_usage,
+        cpu_usage_normalized: sum.cpu_usage_normalized + row.cpu_usage_normalized,
+        num_cpus: sum.num_cpus,
+        system_cpu_usage: sum.system_cpu_usage,
+        system_memory_used: sum.system_memory_used,
+        system_memory_total: sum.system_memory_total,
+        res_memory_usage: sum.res_memory_usage + row.res_memory_usage,
+        anon_memory_usage: sum
+            .anon_memory_usage
+            .zip(row.anon_memory_usage)
+            .map(|(a, b)| a + b),
+        file_memory_usage: sum
+            .file_memory_usage
+            .zip(row.file_memory_usage)
+            .map(|(a, b)| a + b),
+        swap_usage: sum.swap_usage.zip(row.swap_usage).map(|(a, b)| a + b),
+        minor_page_faults: sum
+            .minor_page_faults
+            .zip(row.minor_page_faults)
+            .map(|(a, b)| a + b),
+        major_page_faults: sum
+            .major_page_faults
+            .zip(row.major_page_faults)
+            .map(|(a, b)| a + b),
+        open_fds: sum.open_fds.zip(row.open_fds).map(|(a, b)| a + b),
+        threads: sum.threads.zip(row.threads).map(|(a, b)| a + b),
+        disk_read: sum.disk_read + row.disk_read,
+        disk_write: sum.disk_write + row.disk_write,
+        disk_read_per_sec: sum.disk_read_per_sec + row.disk_read_per_sec,
+        disk_write_per_sec: sum.disk_write_per_sec + row.disk_write_per_sec,
+        disk_read_total: sum.disk_read_total + row.disk_read_total,
+        disk_write_total: sum.disk_write_total + row.disk_write_total,
+        net_rx_total: sum.net_rx_total,
+        net_tx_total: sum.net_tx_total,
+        net_rx_per_sec: sum.net_rx_per_sec,
+        net_tx_per_sec: sum.net_tx_per_sec,
+        gc_live: sum.gc_live,
+        gc_pause: sum.gc_pause,
+        allocation_rate: sum.allocation_rate,
+        smoothed_cpu_usage: sum.smoothed_cpu_usage,
+        smoothed_memory_usage: sum.smoothed_memory_usage,
+    })
+}
+
+/// The fields of `/proc/<pid>/status` that are in the rows.
+#[derive(Default)]
+struct ProcStatus {
+    /// The anonymous and file backed resident memory of the process in kb.
+    anon_memory: Option<u64>,
+    file_memory: Option<u64>,
+    /// The swapped out memory of the process in kb.
+    swap: Option<u64>,
+    threads: Option<u64>,
+}
+
+#[cfg(target_os = "linux")]
+fn proc_status(pid: Pid) -> anyhow::Result<ProcStatus> {
+    let proc_status_contents = fs::read_to_string(format!("/proc/{}/status", pid)).context(
+        "Unable to read from /proc. Is the process running? Or are you not running as sudo?",
+    )?;
+
+    let mut anon_mem = None;
+    let mut file_mem = None;
+    let mut swap = None;
+    let mut threads = None;
+    for line in proc_status_contents.lines() {
+        if line.contains("RssAnon") {
+            let rss_anon = line.chars().filter(|c| c.is_numeric()).collect::<String>();
+            anon_mem = Some(rss_anon.parse::<u64>().context("Cannot parse RssAnon")?);
+        } else if line.contains("RssFile") {
+            let rss_file = line.chars().filter(|c| c.is_numeric()).collect::<String>();
+            file_mem = Some(rss_file.parse::<u64>().context("Cannot parse RssFile")?);
+        } else if line.starts_with("VmSwap:") {
+            let vm_swap = line.chars().filter(|c| c.is_numeric()).collect::<String>();
+            swap = Some(vm_swap.parse::<u64>().context("Cannot parse VmSwap")?);
+        } else if line.starts_with("Threads:") {
+            let count = line.chars().filter(|c| c.is_numeric()).collect::<String>();
+            threads = Some(count.parse::<u64>().context("Cannot parse Threads")?);
+        }
+    }
+    anyhow::ensure!(anon_mem.is_some(), "Could not retrieve RssAnon");
+    anyhow::ensure!(file_mem.is_some(), "Could not retrieve RssFile");
+    anyhow::ensure!(threads.is_some(), "Could not retrieve Threads");
+    Ok(ProcStatus {
+        anon_memory: anon_mem,
+        file_memory: file_mem,
+        // kernel threads have no memory and thus no VmSwap.
+        swap,
+        threads,
+    })
+}
+
+/// The status of a process is only available from /proc on Linux.
+#[cfg(not(target_os = "linux"))]
+fn proc_status(_pid: Pid) -> anyhow::Result<ProcStatus> {
+    Ok(ProcStatus::default())
+}
+
+/// The minor and major page faults of the process since it started.
+#[cfg(target_os = "linux")]
+fn page_faults(pid: Pid) -> anyhow::Result<Option<(u64, u64)>> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid))
+        .context("Unable to read the page faults from /proc.")?;
+    // the name of the process is in parentheses and may contain spaces, the
+    // fields after it start with the state, which is the third field.
+    let (_, fields) = stat.rsplit_once(')').context("Cannot parse stat")?;
+    let fields: Vec<&str> = fields.split_whitespace().collect();
+    anyhow::ensure!(fields.len() >= 10, "Cannot parse stat");
+    // minflt and majflt are the 10th and 12th field.
+    let minor = fields[7].parse::<u64>().context("Cannot parse minflt")?;
+    let major = fields[9].parse::<u64>().context("Cannot parse majflt")?;
+    Ok(Some((minor, major)))
+}
+
+/// The page faults are only available from /proc on Linux.
+#[cfg(not(target_os = "linux"))]
+fn page_faults(_pid: Pid) -> anyhow::Result<Option<(u64, u64)>> {
+    Ok(None)
+}
+
+/// The number of file descriptors the process has open, sockets included.
+#[cfg(target_os = "linux")]
+fn open_fds(pid: Pid) -> anyhow::Result<Option<u64>> {
+    let fds = fs::read_dir(format!("/proc/{}/fd", pid))
+        .context("Unable to read the open file descriptors from /proc.")?;
+    Ok(Some(fds.count() as u64))
+}
+
+/// The open file descriptors are only available from /proc on Linux.
+#[cfg(not(target_os = "linux"))]
+fn open_fds(_pid: Pid) -> anyhow::Result<Option<u64>> {
+    Ok(None)
+}
+
+/// The bytes received and sent over all interfaces, including loopback, of
+/// the network namespace of the process.
+#[cfg(target_os = "linux")]
+fn net_counters(pid: Pid) -> anyhow::Result<Option<(u64, u64)>> {
+    let net_dev = fs::read_to_string(format!("/proc/{}/net/dev", pid))
+        .context("Unable to read the network counters from /proc.")?;
+    let mut rx = 0;
+    let mut tx = 0;
+    // the first two lines are headers.
+    for line in net_dev.lines().skip(2) {
+        let (_, counters) = line.split_once(':').context("Cannot parse net/dev")?;
+        let counters: Vec<&str> = counters.split_whitespace().collect();
+        anyhow::ensure!(counters.len() >= 9, "Cannot parse net/dev");
+        rx += counters[0].parse::<u64>().context("Cannot parse net/dev")?;
+        tx += counters[8].parse::<u64>().context("Cannot parse net/dev")?;
+    }
+    Ok(Some((rx, tx)))
+}
+
+/// The network counters are only available from /proc on Linux.
+#[cfg(not(target_os = "linux"))]
+fn net_counters(_pid: Pid) -> anyhow::Result<Option<(u64, u64)>> {
+    Ok(None)
+}
+
+/// Show a memory usage in kb as MB, or `-` if it is not measured.
+fn display_mb(kb: Option<u64>) -> String {
+    kb.map_or_else(|| "-".to_string(), |kb| (kb / 1000).to_string())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    /// A row of `pid` with only the columns that are always measured.
+    pub(crate) fn row(pid: &str) -> Row {
+        Row {
+            label: None,
+            time: Timestamp {
+                time: Utc.timestamp_opt(0, 0).unwrap(),
+                format: TimeFormat::Rfc3339,
+            },
+            pid: pid.to_string(),
+            cpu_usage: 50.0,
+            cpu_usage_normalized: 25.0,
+            num_cpus: 2,
+            system_cpu_usage: 75.0,
+            system_memory_used: 4000,
+            system_memory_total: 8000,
+            res_memory_usage: 1000,
+            anon_memory_usage: None,
+            file_memory_usage: None,
+            swap_usage: None,
+            minor_page_faults: None,
+            major_page_faults: None,
+            open_fds: None,
+            threads: None,
+            disk_read: 10,
+            disk_write: 20,
+            disk_read_per_sec: 1.0,
+            disk_write_per_sec: 2.0,
+            disk_read_total: 100,
+            disk_write_total: 200,
+            net_rx_total: None,
+            net_tx_total: None,
+            net_rx_per_sec: None,
+            net_tx_per_sec: None,
+            gc_live: None,
+            gc_pause: None,
+            allocation_rate: None,
+            smoothed_cpu_usage: None,
+            smoothed_memory_usage: None,
+        }
+    }
+
+    #[test]
+    fn window_evicts_the_oldest_sample() {
+        let mut window = Window::default();
+        assert_eq!(window.push(2, 10.0, 100), (10.0, 100));
+        assert_eq!(window.push(2, 30.0, 300), (20.0, 200));
+        // the first sample falls out of the window.
+        assert_eq!(window.push(2, 50.0, 500), (40.0, 400));
+        assert_eq!(window.samples.len(), 2);
+    }
+
+    #[test]
+    fn limits_are_checked_against_the_raw_sample() {
+        let cfg = Config::from_iter([
+            "process-metrics",
+            "--pid",
+            "1",
+            "--max-rss-mb",
+            "2",
+            "--max-cpu-pct",
+            "80",
+            "--smooth",
+            "3",
+        ]);
+        let mut sample = row("1");
+        assert!(exceeded_limits(&cfg, &sample).is_empty());
+        // a spike the averages hide still exceeds the limits.
+        sample.res_memory_usage = 3000;
+        sample.cpu_usage = 90.0;
+        sample.smoothed_memory_usage = Some(1500);
+        sample.smoothed_cpu_usage = Some(60.0);
+        assert_eq!(
+            exceeded_limits(&cfg, &sample),
+            ["res memory 3 MB > 2 MB", "cpu 90.0% > 80%"]
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use crate::Row;
+use anyhow::Context;
+use std::fmt::Write;
+use std::path::Path;
+
+/// A gauge with the value of a column of a row, if it was measured.
+struct Metric {
+    name: &'static str,
+    help: &'static str,
+    value: fn(&Row) -> Option<f64>,
+}
+
+const METRICS: &[Metric] = &[
+    Metric {
+        name: "process_cpu_usage",
+        help: "Cpu usage of the process in percent, 100 is one busy core.",
+        value: |row| Some(row.cpu_usage.into()),
+    },
+    Metric {
+        name: "process_cpu_usage_normalized",
+        help: "Cpu usage of the process divided by the number of cpus, in percent.",
+        value: |row| Some(row.cpu_usage_normalized.into()),
+    },
+    Metric {
+        name: "process_rss_kb",
+        help: "Resident memory of the process in kb.",
+        value: |row| Some(row.res_memory_usage as f64),
+    },
+    Metric {
+        name: "process_anon_kb",
+        help: "Anonymous resident memory of the process in kb.",
+        value: |row| row.anon_memory_usage.map(|kb| kb as f64),
+    },
+    Metric {
+        name: "process_file_kb",
+        help: "File backed resident memory of the process in kb.",
+        value: |row| row.file_memory_usage.map(|kb| kb as f64),
+    },
+    Metric {
+        name: "process_swap_kb",
+        help: "Swapped out memory of the process in kb.",
+        value: |row| row.swap_usage.map(|kb| kb as f64),
+    },
+    Metric {
+        name: "process_minor_page_faults",
+        help: "Minor page faults of the process since it started.",
+        value: |row| row.minor_page_faults.map(|faults| faults as f64),
+    },
+    Metric {
+        name: "process_major_page_faults",
+        help: "Major page faults of the process since it started, which had to read from disk.",
+        value: |row| row.major_page_faults.map(|faults| faults as f64),
+    },
+    Metric {
+        name: "process_open_fds",
+        help: "Open file descriptors of the process.",
+        value: |row| row.open_fds.map(|fds| fds as f64),
+    },
+    Metric {
+        name: "process_threads",
+        help: "Threads of the process.",
+        value: |row| row.threads.map(|threads| threads as f64),
+    },
+    Metric {
+        name: "process_disk_read_kb_per_second",
+        help: "Bytes read from disk by the process in kb/s.",
+        value: |row| Some(row.disk_read_per_sec),
+    },
+    Metric {
+        name: "process_disk_write_kb_per_second",
+        help: "Bytes written to disk by the process in kb/s.",
+        value: |row| Some(row.disk_write_per_sec),
+    },
+    Metric {
+        name: "process_disk_read_total_kb",
+        help: "Bytes read from disk by the process since it started in kb.",
+        value: |row| Some(row.disk_read_total as f64),
+    },
+    Metric {
+        name: "process_disk_write_total_kb",
+        help: "Bytes written to disk by the process since it started in kb.",
+        value: |row| Some(row.disk_write_total as f64),
+    },
+    Metric {
+        name: "process_net_receive_kb_per_second",
+        help: "Bytes received in the network namespace of the process in kb/s.",
+        value: |row| row.net_rx_per_sec,
+    },
+    Metric {
+        name: "process_net_send_kb_per_second",
+        help: "Bytes sent in the network namespace of the process in kb/s.",
+        value: |row| row.net_tx_per_sec,
+    },
+    Metric {
+        name: "process_gc_live_kb",
+        help: "Live bytes after the latest garbage collection in kb.",
+        value: |row| row.gc_live.map(|kb| kb as f64),
+    },
+    Metric {
+        name: "process_gc_pause_ms",
+        help: "Time spent in garbage collections during the interval in milliseconds.",
+        value: |row| row.gc_pause,
+    },
+    Metric {
+        name: "process_allocation_rate_kb_per_second",
+        help: "Bytes allocated by the process in kb/s.",
+        value: |row| row.allocation_rate,
+    },
+    Metric {
+        name: "system_cpu_usage",
+        help: "Cpu usage of the whole system in percent.",
+        value: |row| Some(row.system_cpu_usage.into()),
+    },
+    Metric {
+        name: "system_memory_used_kb",
+        help: "Memory used by the whole system in kb.",
+        value: |row| Some(row.system_memory_used as f64),
+    },
+];
+
+/// The `rows` of the latest sample in the Prometheus text exposition format,
+/// with the pid and the label of every row as labels.
+fn exposition(rows: &[Row]) -> String {
+    let mut text = String::new();
+    for metric in METRICS {
+        let values: Vec<(&Row, f64)> = rows
+            .iter()
+            .filter_map(|row| Some((row, (metric.value)(row)?)))
+            .collect();
+        if values.is_empty() {
+            continue;
+        }
+        // writing to a string cannot fail.
+        let _ = writeln!(text, "# HELP {} {}", metric.name, metric.help);
+        let _ = writeln!(text, "# TYPE {} gauge", metric.name);
+        for (row, value) in values {
+            let _ = write!(text, "{}{{pid=\"{}\"", metric.name, escape(&row.pid));
+            if let Some(label) = &row.label {
+                let _ = write!(text, ",label=\"{}\"", escape(label));
+            }
+            let _ = writeln!(text, "}} {}", value);
+        }
+    }
+    text
+}
+
+/// Escape a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Replace the file at `path` with the `rows` of the latest sample. The file
+/// is written next to it first and then renamed, so the textfile collector of
+/// node_exporter never reads a partial file.
+pub(crate) fn write_textfile(path: &Path, rows: &[Row]) -> anyhow::Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".tmp");
+    std::fs::write(&partial, exposition(rows))
+        .context(format!("cannot write the metrics to {:?}", partial))?;
+    std::fs::rename(&partial, path)
+        .context(format!("cannot write the metrics to {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::row;
+
+    #[test]
+    fn exposition_has_a_gauge_per_measured_column() {
+        let mut labeled = row("2");
+        labeled.label = Some("node \"a\"".to_string());
+        let text = exposition(&[row("1"), labeled]);
+        let lines: Vec<_> = text.lines().collect();
+        assert!(lines.contains(&"# TYPE process_disk_read_total_kb gauge"));
+        assert!(lines.contains(&"process_disk_read_total_kb{pid=\"1\"} 100"));
+        assert!(lines.contains(&"process_disk_write_total_kb{pid=\"1\"} 200"));
+        assert!(lines.contains(&"process_cpu_usage{pid=\"2\",label=\"node \\\"a\\\"\"} 50"));
+        // the columns that are not measured have no gauge.
+        assert!(!text.contains("process_gc_live_kb"));
+    }
+}
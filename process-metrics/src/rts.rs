@@ -0,0 +1,131 @@
+use anyhow::Context;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// The garbage collector statistics of a Haskell process since the previous
+/// sample.
+pub(crate) struct GcSample {
+    /// The live bytes after the latest collection, in kb.
+    pub(crate) live: Option<u64>,
+    /// The time spent in collections, in milliseconds.
+    pub(crate) pause_millis: f64,
+    /// The bytes allocated per second, in kb/s.
+    pub(crate) allocation_rate: f64,
+}
+
+/// Reads the statistics the GHC runtime writes for every collection when the
+/// program runs with `+RTS -S<file>`. The file is read incrementally, so every
+/// sample only covers the collections since the previous one.
+pub(crate) struct RtsLog {
+    path: PathBuf,
+    /// The position up to which the file has been read.
+    offset: u64,
+    /// The start of a line that has not been completely written yet.
+    pending: String,
+    live: Option<u64>,
+    last_read: Instant,
+}
+
+impl RtsLog {
+    /// Start reading the collections that are written to `path` from now on.
+    pub(crate) fn open(path: PathBuf) -> anyhow::Result<Self> {
+        let offset = std::fs::metadata(&path)
+            .context(format!("cannot read RTS statistics {}", path.display()))?
+            .len();
+        Ok(RtsLog {
+            path,
+            offset,
+            pending: String::new(),
+            live: None,
+            last_read: Instant::now(),
+        })
+    }
+
+    /// The statistics of the collections since the previous sample.
+    pub(crate) fn sample(&mut self) -> anyhow::Result<GcSample> {
+        let mut file = File::open(&self.path).context(format!(
+            "cannot read RTS statistics {}",
+            self.path.display()
+        ))?;
+        // a shorter file means the node was restarted and the file recreated.
+        if file.metadata()?.len() < self.offset {
+            self.offset = 0;
+            self.pending.clear();
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut new = String::new();
+        self.offset += file.read_to_string(&mut new)? as u64;
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_read).as_secs_f64();
+        self.last_read = now;
+
+        self.pending.push_str(&new);
+        let complete = self.pending.rfind('\n').map_or(0, |end| end + 1);
+        let lines: String = self.pending.drain(..complete).collect();
+        let mut allocated = 0;
+        let mut pause = 0.0;
+        for collection in lines.lines().filter_map(parse_collection) {
+            allocated += collection.allocated;
+            pause += collection.elapsed;
+            self.live = Some(collection.live / 1000);
+        }
+        Ok(GcSample {
+            live: self.live,
+            pause_millis: pause * 1000.0,
+            allocation_rate: allocated as f64 / 1000.0 / elapsed,
+        })
+    }
+}
+
+/// A single collection in the RTS statistics.
+struct Collection {
+    allocated: u64,
+    live: u64,
+    /// The wall clock time of the collection in seconds.
+    elapsed: f64,
+}
+
+/// Parse a line of the form `Alloc Copied Live GCuser GCelap TOTuser TOTelap
+/// ...`. The headers and the summary at exit do not parse and are skipped.
+fn parse_collection(line: &str) -> Option<Collection> {
+    let mut fields = line.split_whitespace();
+    let allocated = fields.next()?.parse().ok()?;
+    let _copied: u64 = fields.next()?.parse().ok()?;
+    let live = fields.next()?.parse().ok()?;
+    let _user: f64 = fields.next()?.parse().ok()?;
+    let elapsed = fields.next()?.parse().ok()?;
+    Some(Collection {
+        allocated,
+        live,
+        elapsed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collections_are_parsed() {
+        let line =
+            "   1048576     15536     67456  0.000  0.002    0.010    0.012    0    0  (Gen:  0)";
+        let collection = parse_collection(line).unwrap();
+        assert_eq!(collection.allocated, 1048576);
+        assert_eq!(collection.live, 67456);
+        assert_eq!(collection.elapsed, 0.002);
+    }
+
+    #[test]
+    fn headers_and_summary_are_skipped() {
+        for line in [
+            "    Alloc    Copied     Live     GC     GC      TOT      TOT  Page Flts",
+            "    bytes     bytes     bytes   user   elap     user     elap",
+            "     290,328 bytes allocated in the heap",
+            "",
+        ] {
+            assert!(parse_collection(line).is_none(), "{:?}", line);
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Serializer};
+use std::sync::Arc;
+
+/// How the time of a sample is written.
+#[derive(Clone)]
+pub(crate) enum TimeFormat {
+    Rfc3339,
+    /// Unix seconds.
+    Epoch,
+    /// A chrono format string.
+    Custom(Arc<str>),
+}
+
+impl TimeFormat {
+    /// The format chosen with `--time-format` or `--epoch`.
+    pub(crate) fn new(format: Option<&str>, epoch: bool) -> anyhow::Result<TimeFormat> {
+        match format {
+            _ if epoch => Ok(TimeFormat::Epoch),
+            Some(format) => {
+                // an invalid format would only fail when the first row is written.
+                anyhow::ensure!(
+                    !StrftimeItems::new(format).any(|item| matches!(item, Item::Error)),
+                    "invalid --time-format {:?}",
+                    format
+                );
+                Ok(TimeFormat::Custom(format.into()))
+            }
+            None => Ok(TimeFormat::Rfc3339),
+        }
+    }
+}
+
+/// The time of a sample, serialized in its format.
+#[derive(Clone)]
+pub(crate) struct Timestamp {
+    pub(crate) time: DateTime<Utc>,
+    pub(crate) format: TimeFormat,
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.format {
+            TimeFormat::Rfc3339 => self.time.serialize(serializer),
+            TimeFormat::Epoch => serializer.serialize_i64(self.time.timestamp()),
+            TimeFormat::Custom(format) => serializer.collect_str(&self.time.format(format)),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::Context;
+use clap::AppSettings;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(
+    name = "concordium-toolbox",
+    about = "Tools for running and measuring a local Concordium network."
+)]
+struct Toolbox {
+    #[structopt(
+        long = "out",
+        help = "File to write the rows of the blocks, procmetrics and logs subcommands to, unless the subcommand is given --out itself."
+    )]
+    out: Option<PathBuf>,
+    #[structopt(
+        long = "format",
+        help = "Format of the rows of the blocks, procmetrics and logs subcommands: csv, json or jsonl, unless the subcommand is given --format itself."
+    )]
+    format: Option<String>,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+// the options are parsed once, so the size of the variants does not matter.
+#[allow(clippy::large_enum_variant)]
+#[derive(StructOpt)]
+enum Command {
+    #[structopt(
+        name = "spawn",
+        about = "Spawn a local network of nodes and follow their logs."
+    )]
+    Spawn(chain::Config),
+    #[structopt(
+        name = "blocks",
+        alias = "analyze-blocks",
+        about = "Collect block timings from the gRPC interface of the nodes."
+    )]
+    Blocks(block_analyzer::App),
+    #[structopt(
+        name = "procmetrics",
+        alias = "metrics",
+        about = "Sample the resource usage of a running process."
+    )]
+    ProcMetrics(process_metrics::Config),
+    #[structopt(
+        name = "logs",
+        alias = "analyze-logs",
+        about = "Extract metrics from node log files."
+    )]
+    Logs(log_analyzer::Config),
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> anyhow::Result<()> {
+    let matches = Toolbox::clap()
+        .global_setting(AppSettings::ColoredHelp)
+        .get_matches();
+    let toolbox = Toolbox::from_clap(&matches);
+    let command_matches = matches
+        .subcommand()
+        .1
+        .context("missing subcommand options")?;
+
+    // the options given to the subcommand take precedence over the shared ones.
+    let out = toolbox
+        .out
+        .filter(|_| command_matches.occurrences_of("out") == 0);
+    let format = toolbox
+        .format
+        .as_deref()
+        .filter(|_| command_matches.occurrences_of("format") == 0);
+
+    match toolbox.command {
+        Command::Spawn(cfg) => {
+            anyhow::ensure!(
+                out.is_none() && format.is_none(),
+                "spawn does not write rows, --out and --format do not apply to it"
+            );
+            // the options given on the command line take precedence over the config file.
+            chain::run(cfg.with_config_file(command_matches)?).await
+        }
+        Command::Blocks(app) => block_analyzer::run(app.with_output(out, format)?).await,
+        Command::ProcMetrics(cfg) => process_metrics::run(cfg.with_output(out, format)?),
+        Command::Logs(cfg) => log_analyzer::run(cfg.with_output(out, format)?),
+    }
+}